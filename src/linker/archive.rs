@@ -53,7 +53,7 @@ impl ArHdr {
 
 
 pub fn ReadArchiveMembers(file: Rc<File>) -> Vec<Rc<File>>{
-	assert!(file.Type == FileType::FileTypeArchive);
+	assert!(file.Type == FileType::Archive);
 
 	let mut pos = 8;	
 	let mut strTab = ByteSequence::default();
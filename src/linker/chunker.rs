@@ -1,14 +1,19 @@
 use super::common::*;
-use super::elf::MAGIC;
+use super::elf::{MAGIC, Sym, Dyn, Rela};
+use super::relocation::{writeUtype, writeItype};
+use super::ehframe::{CollectFdes, EhFrameSections};
 use super::output::{
-    Chunk, MergedSection, OutputEhdr, OutputShdr, OutputSection, OutputPhdr,
-    GetEntryAddr, GetFlags, ptr2ref_dyn, createPhdr,
+    Chunk, MergedSection, OutputEhdr, OutputShdr, OutputSection, OutputPhdr, OutputShstrtab, OutputSymtab, OutputStrtab,
+    OutputDynamic, OutputRelaDyn, OutputInterp, OutputDynsym, OutputDynstr, OutputHash, OutputGnuHash,
+    OutputGot, OutputGotPlt, OutputPlt, OutputRelaPlt, OutputDynbss, OutputIplt, OutputRelaIplt, OutputEhFrameHdr,
+    ElfHash, GnuHash, GetEntryAddr, GetFlags, ptr2ref_dyn, createPhdr,
 };
 
 pub trait Chunker {
 	fn GetShdr(&mut self) -> &mut Shdr;
 	fn GetName(&self) -> &String;
 	fn GetShndx(&self) -> usize;
+	fn SetShndx(&mut self, shndx: usize);
 
 	// use raw pointer to avoid some borrow checks
 	/// get some data from the chunk and copy it to a buffer(usually ctx.Buf)
@@ -50,6 +55,7 @@ impl Chunker for Chunk {
 	fn GetName(&self) -> &String { &self.Name }
 	fn GetShdr(&mut self) -> &mut Shdr 	{ &mut self.Shdr }
 	fn GetShndx(&self) -> usize { self.Shndx }
+	fn SetShndx(&mut self, shndx: usize) { self.Shndx = shndx }
 }
 
 impl Chunker for OutputEhdr {
@@ -61,7 +67,10 @@ impl Chunker for OutputEhdr {
 		ehdr.Ident[abi::EI_VERSION] = abi::EV_CURRENT;
 		ehdr.Ident[abi::EI_OSABI] = 0;
 		ehdr.Ident[abi::EI_ABIVERSION] = 0;
-		ehdr.Type = abi::ET_EXEC;
+		ehdr.Type = match ptr2ref(ctx).Args.Pie {
+			true => abi::ET_DYN,
+			false => abi::ET_EXEC,
+		};
 		ehdr.Machine = abi::EM_RISCV;
 		ehdr.Version = abi::EV_CURRENT as u32;
 		ehdr.Entry = GetEntryAddr(ctx);
@@ -74,6 +83,7 @@ impl Chunker for OutputEhdr {
         ehdr.PhNum = (ptr2ref(ctx).Phdr.Shdr.Size / PHDR_SIZE) as u16;
 		ehdr.ShEntSize = SHDR_SIZE as u16;
 		ehdr.ShNum = (ptr2ref(ctx).Shdr.Shdr.Size / SHDR_SIZE) as u16;
+		ehdr.ShStrndx = ptr2ref(ctx).Shstrtab.Shndx as u16;
 
 		let ehdr_ptr = std::ptr::addr_of!(ehdr) as *const u8;
 		let ctx = ptr2ref(ctx);
@@ -85,13 +95,16 @@ impl Chunker for OutputEhdr {
 
 	fn UpdateShdr(&mut self, _: *mut Box<Context>) {/* do nothing */}
 	fn GetShndx(&self) -> usize { self.Chunk.GetShndx()}
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
 	fn GetShdr(&mut self) -> &mut Shdr 	{ self.Chunk.GetShdr() }
 	fn GetName(&self) -> &String { self.Chunk.GetName() }
 }
 
 impl Chunker for OutputPhdr {
     fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
-        // bug here. write fails to work?
+        // the first round in `UpdateShdr` only decides the size of the table.
+        // addresses and offsets are known by now, so build the real ones
+        self.Phdrs = createPhdr(Box::as_mut(unsafe{&mut *ctx}));
         let buf = &mut ptr2ref(ctx).Buf[self.Shdr.Offset..];
         let data = vec2slice(&self.Phdrs);
         buf[..self.Shdr.Size].copy_from_slice(data);
@@ -106,6 +119,7 @@ impl Chunker for OutputPhdr {
     fn GetName(&self) -> &String { self.Chunk.GetName() }
     fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
     fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+    fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
 }
 
 impl Chunker for OutputShdr {
@@ -139,6 +153,7 @@ impl Chunker for OutputShdr {
 	fn GetName(&self) -> &String { &self.Chunk.GetName() }
 	fn GetShdr(&mut self) -> &mut Shdr 	{ self.Chunk.GetShdr() }
 	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
 }
 
 impl Chunker for OutputSection {
@@ -147,12 +162,14 @@ impl Chunker for OutputSection {
 			return;
 		}
 
+		let base = &mut ptr2ref(ctx).Buf[self.Shdr.Offset..];
 		let ctx = ptr2ref(ctx);
-		let base = &mut ctx.Buf[self.Shdr.Offset..];
 		for isec in &self.Members {
-			let mut isec = isec.borrow_mut();
+			// symbols used by relocations may point back to this isec,
+			// so only take a shared borrow here
+			let isec = isec.borrow();
 			let buf = &mut base[isec.Offset..];
-			isec.WriteTo(buf);
+			isec.WriteTo(ctx, buf);
 		}
 	}
 
@@ -160,6 +177,7 @@ impl Chunker for OutputSection {
 	fn GetName(&self) -> &String { &self.Chunk.GetName() }
 	fn GetShdr(&mut self) -> &mut Shdr  { self.Chunk.GetShdr() }
 	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
 }
 
 impl Chunker for MergedSection {
@@ -176,5 +194,576 @@ impl Chunker for MergedSection {
 	fn GetName(&self) -> &String { self.Chunk.GetName() }
 	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
 	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+
+}
+
+impl Chunker for OutputShstrtab {
+	fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let buf = &mut ctx.Buf[self.Shdr.Offset..];
+		buf[..self.Contents.len()].copy_from_slice(&self.Contents);
+	}
+
+	/// collect the names of all the sections, and tell each of them where its name is
+	fn UpdateShdr(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		self.Contents = vec![0];
+		for c in &ctx.Chunks {
+			let c = ptr2ref_dyn(*c);
+			if c.GetShndx() == 0 {
+				continue;
+			}
+
+			c.GetShdr().Name = self.Contents.len() as u32;
+			self.Contents.extend_from_slice(c.GetName().as_bytes());
+			self.Contents.push(0);
+		}
+		self.Shdr.Size = self.Contents.len();
+	}
+
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+}
+
+impl Chunker for OutputSymtab {
+	fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let base = &mut ctx.Buf[self.Shdr.Offset..];
+		Write::<Sym>(base, &Sym{..Default::default()});
+
+		for (i, sym) in self.Symbols.iter().enumerate() {
+			let sym = sym.borrow();
+			let esym = sym.ElfSym();
+			let mut info = esym.Info;
+			let mut other = esym.Other;
+			if esym.Bind() != abi::STB_LOCAL {
+				other = (other & !0b11) | sym.Visibility;
+				if sym.IsHidden() {
+					info = abi::STB_LOCAL << 4 | esym.Type();
+				}
+			}
+
+			// an IFUNC symbol is just a function at its .iplt entry in the output
+			let mut shndx = OutputSymtab::GetOutputShndx(&sym);
+			if sym.IpltIdx.is_some() {
+				info = (info & !0xf) | abi::STT_FUNC;
+				shndx = ctx.Iplt.Shndx as u16;
+			}
+
+			let out = Sym {
+				Name:	self.NameOffsets[i],
+				Info:	info,
+				Other:	other,
+				Shndx:	shndx,
+				Val:	sym.GetAddr(),
+				Size:	esym.Size,
+			};
+			Write::<Sym>(&mut base[(i + 1) * self.Shdr.EntSize..], &out);
+		}
+	}
+
+	/// decide which symbols go to the output, and put their names into .strtab
+	fn UpdateShdr(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		self.Symbols.clear();
+		for obj in &ctx.Objs {
+			for sym in obj.borrow().LocalSymbols.iter().skip(1) {
+				if OutputSymtab::ShouldWrite(&sym.borrow(), &sym.borrow().ElfSym()) {
+					self.Symbols.push(sym.clone());
+				}
+			}
+		}
+
+		// hidden globals are demoted to locals, which must come first
+		let mut globals = vec![];
+		for sym in ctx.SymbolMap.values() {
+			let s = sym.borrow();
+			if !s.FileAlive() || !OutputSymtab::ShouldWrite(&s, &s.ElfSym()) {
+				continue;
+			}
+			if s.IsHidden() {
+				self.Symbols.push(sym.clone());
+			}
+			else {
+				globals.push(sym.clone());
+			}
+		}
+		let numLocals = self.Symbols.len() + 1;
+		self.Symbols.extend(globals);
+
+		let strtab = &mut ctx.Strtab.Contents;
+		*strtab = vec![0];
+		self.NameOffsets.clear();
+		for sym in &self.Symbols {
+			self.NameOffsets.push(strtab.len() as u32);
+			strtab.extend_from_slice(sym.borrow().Name.as_bytes());
+			strtab.push(0);
+		}
+		ctx.Strtab.Shdr.Size = strtab.len();
+
+		self.Shdr.Size = (self.Symbols.len() + 1) * self.Shdr.EntSize;
+		self.Shdr.Link = ctx.Strtab.Shndx as u32;
+		self.Shdr.Info = numLocals as u32;
+	}
+
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+}
+
+impl Chunker for OutputStrtab {
+	fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let buf = &mut ctx.Buf[self.Shdr.Offset..];
+		buf[..self.Contents.len()].copy_from_slice(&self.Contents);
+	}
+
+	fn UpdateShdr(&mut self, _ctx: *mut Box<Context>) {/* done by symtab */}
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+}
+
+impl Chunker for OutputDynamic {
+	fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let entries = OutputDynamic::GetEntries(ctx);
+		let buf = &mut ctx.Buf[self.Shdr.Offset..];
+		for (i, entry) in entries.iter().enumerate() {
+			Write::<Dyn>(&mut buf[i * self.Shdr.EntSize..], entry);
+		}
+	}
+
+	fn UpdateShdr(&mut self, ctx: *mut Box<Context>) {
+		self.Shdr.Size = OutputDynamic::GetEntries(ptr2ref(ctx)).len() * self.Shdr.EntSize;
+	}
+
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+}
+
+impl Chunker for OutputRelaDyn {
+	fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let entries = OutputRelaDyn::GetEntries(ctx);
+		let buf = &mut ctx.Buf[self.Shdr.Offset..];
+		for (i, entry) in entries.iter().enumerate() {
+			Write::<Rela>(&mut buf[i * self.Shdr.EntSize..], entry);
+		}
+	}
+
+	fn UpdateShdr(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		self.Shdr.Size = OutputRelaDyn::GetEntries(ctx).len() * self.Shdr.EntSize;
+		self.Shdr.Link = ctx.Dynsym.Shndx as u32;
+	}
+
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+}
+
+impl Chunker for OutputInterp {
+	fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let path = ctx.Args.DynamicLinker.as_bytes();
+		let buf = &mut ctx.Buf[self.Shdr.Offset..];
+		buf[..path.len()].copy_from_slice(path);
+		buf[path.len()] = 0;
+	}
+
+	fn UpdateShdr(&mut self, ctx: *mut Box<Context>) {
+		self.Shdr.Size = ptr2ref(ctx).Args.DynamicLinker.len() + 1;
+	}
+
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+}
+
+impl Chunker for OutputDynsym {
+	fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let base = &mut ctx.Buf[self.Shdr.Offset..];
+		Write::<Sym>(base, &Sym{..Default::default()});
+
+		for (i, sym) in self.Symbols.iter().enumerate() {
+			let sym = sym.borrow();
+			let esym = sym.ElfSym();
+			// an imported function with a canonical PLT entry is known by that address
+			let (bind, shndx, val) = match (sym.IsImported(), sym.HasCopyrel) {
+				(true, false) => (abi::STB_GLOBAL, abi::SHN_UNDEF, if sym.IsCanonical { sym.GetAddr() } else { 0 }),
+				(true, true) => (abi::STB_GLOBAL, ctx.Dynbss.Shndx as u16, sym.GetAddr()),
+				_ if sym.IpltIdx.is_some() => (esym.Bind(), ctx.Iplt.Shndx as u16, sym.GetAddr()),
+				_ => (esym.Bind(), OutputSymtab::GetOutputShndx(&sym), sym.GetAddr()),
+			};
+			// the .iplt entry is a plain function
+			let ty = if sym.IpltIdx.is_some() { abi::STT_FUNC } else { esym.Type() };
+
+			let out = Sym {
+				Name:	self.NameOffsets[i],
+				Info:	bind << 4 | ty,
+				Other:	abi::STV_DEFAULT,
+				Shndx:	shndx,
+				Val:	val,
+				Size:	esym.Size,
+			};
+			Write::<Sym>(&mut base[(i + 1) * self.Shdr.EntSize..], &out);
+		}
+	}
+
+	fn UpdateShdr(&mut self, ctx: *mut Box<Context>) {
+		self.Shdr.Size = (self.Symbols.len() + 1) * self.Shdr.EntSize;
+		self.Shdr.Link = ptr2ref(ctx).Dynstr.Shndx as u32;
+	}
+
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+}
+
+impl Chunker for OutputDynstr {
+	fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let buf = &mut ctx.Buf[self.Shdr.Offset..];
+		buf[..self.Contents.len()].copy_from_slice(&self.Contents);
+	}
+
+	fn UpdateShdr(&mut self, _ctx: *mut Box<Context>) {
+		self.Shdr.Size = self.Contents.len();
+	}
+
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+}
+
+impl Chunker for OutputHash {
+	/// nbucket, nchain, the buckets, and the chains. a bucket holds the first
+	/// symbol with that hash, and the chain of a symbol holds the next one
+	fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let n = ctx.Dynsym.Symbols.len() + 1;
+		let mut buckets = vec![0u32; n];
+		let mut chains = vec![0u32; n];
+		for (i, sym) in ctx.Dynsym.Symbols.iter().enumerate() {
+			let h = ElfHash(&sym.borrow().Name) as usize % n;
+			chains[i + 1] = buckets[h];
+			buckets[h] = (i + 1) as u32;
+		}
+
+		let mut words = vec![n as u32, n as u32];
+		words.extend(buckets);
+		words.extend(chains);
+		let buf = &mut ctx.Buf[self.Shdr.Offset..];
+		buf[..self.Shdr.Size].copy_from_slice(vec2slice(&words));
+	}
+
+	fn UpdateShdr(&mut self, ctx: *mut Box<Context>) {
+		let n = ptr2ref(ctx).Dynsym.Symbols.len() + 1;
+		self.Shdr.Size = (2 + n * 2) * 4;
+		self.Shdr.Link = ptr2ref(ctx).Dynsym.Shndx as u32;
+	}
+
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+}
+
+impl Chunker for OutputGnuHash {
+	/// the header, the bloom filter, the buckets, and the hashes of the symbols
+	/// with the lowest bit marking the last one of each bucket
+	fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let hashes: Vec<u32> = ctx.Dynsym.Symbols[self.SymOffset - 1..].iter()
+			.map(|sym| GnuHash(&sym.borrow().Name))
+			.collect();
+
+		let mut bloom = vec![0u64; self.BloomSize];
+		let mut buckets = vec![0u32; self.NumBuckets];
+		let mut chains = vec![0u32; hashes.len()];
+		for (i, h) in hashes.iter().enumerate() {
+			bloom[(h / 64) as usize % self.BloomSize] |= 1 << (h % 64) | 1 << ((h >> OutputGnuHash::BLOOM_SHIFT) % 64);
+
+			let bucket = *h as usize % self.NumBuckets;
+			if buckets[bucket] == 0 {
+				buckets[bucket] = (self.SymOffset + i) as u32;
+			}
+			chains[i] = h & !1;
+			if hashes.get(i + 1).is_none_or(|next| *next as usize % self.NumBuckets != bucket) {
+				chains[i] |= 1;
+			}
+		}
+
+		let header = vec![self.NumBuckets as u32, self.SymOffset as u32, self.BloomSize as u32, OutputGnuHash::BLOOM_SHIFT];
+		let buf = &mut ctx.Buf[self.Shdr.Offset..];
+		let mut pos = 0;
+		for data in [vec2slice(&header), vec2slice(&bloom), vec2slice(&buckets), vec2slice(&chains)] {
+			buf[pos..pos + data.len()].copy_from_slice(data);
+			pos += data.len();
+		}
+	}
+
+	fn UpdateShdr(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let count = ctx.Dynsym.Symbols.len() + 1 - self.SymOffset;
+		self.Shdr.Size = 16 + self.BloomSize * 8 + (self.NumBuckets + count) * 4;
+		self.Shdr.Link = ctx.Dynsym.Shndx as u32;
+	}
+
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+}
+
+impl Chunker for OutputGot {
+	/// the slots of imported symbols are filled by the dynamic linker, and
+	/// IFUNCs' by R_RISCV_IRELATIVE. TLS variables' slots hold their offsets from tp
+	fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let vals: Vec<u64> = self.Symbols.iter().map(|sym| {
+			let sym = sym.borrow();
+			if sym.IpltIdx.is_some() {
+				return sym.GetDefinedAddr();
+			}
+			match (sym.IsImported(), sym.IsTls()) {
+				(true, _) => 0,
+				(false, true) => sym.GetTpOffset(ctx),
+				(false, false) => sym.GetAddr()
+			}
+		}).collect();
+
+		let buf = &mut ctx.Buf[self.Shdr.Offset..];
+		for (i, val) in vals.iter().enumerate() {
+			Write::<u64>(&mut buf[i * self.Shdr.EntSize..], val);
+		}
+	}
+
+	fn UpdateShdr(&mut self, _ctx: *mut Box<Context>) {
+		self.Shdr.Size = self.Symbols.len() * self.Shdr.EntSize;
+	}
+
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+}
+
+impl Chunker for OutputGotPlt {
+	/// each slot points to the PLT header at first, so the first call goes to the resolver
+	fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let plt = ctx.Plt.Shdr.Addr;
+		let buf = &mut ctx.Buf[self.Shdr.Offset..];
+		for i in 0..ctx.Plt.Symbols.len() {
+			Write::<u64>(&mut buf[OutputGotPlt::HEADER_SIZE + i * self.Shdr.EntSize..], &plt);
+		}
+	}
+
+	fn UpdateShdr(&mut self, ctx: *mut Box<Context>) {
+		self.Shdr.Size = OutputGotPlt::HEADER_SIZE + ptr2ref(ctx).Plt.Symbols.len() * self.Shdr.EntSize;
+	}
+
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+}
+
+impl Chunker for OutputPlt {
+	fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let plt = self.Shdr.Addr;
+		let gotplt = ctx.GotPlt.Shdr.Addr;
+		let buf = &mut ctx.Buf[self.Shdr.Offset..];
+
+		for (i, insn) in PLT_HEADER.iter().enumerate() {
+			Write::<u32>(&mut buf[i * 4..], insn);
+		}
+		let val = gotplt.wrapping_sub(plt) as u32;
+		writeUtype(buf, val);
+		writeItype(&mut buf[8..], val);
+		writeItype(&mut buf[16..], val);
+
+		for i in 0..self.Symbols.len() {
+			let offset = OutputPlt::HEADER_SIZE + i * OutputPlt::ENTRY_SIZE;
+			let entry = &mut buf[offset..];
+			for (j, insn) in PLT_ENTRY.iter().enumerate() {
+				Write::<u32>(&mut entry[j * 4..], insn);
+			}
+			let slot = gotplt + (OutputGotPlt::HEADER_SIZE + i * 8) as u64;
+			let val = slot.wrapping_sub(plt + offset as u64) as u32;
+			writeUtype(entry, val);
+			writeItype(&mut entry[4..], val);
+		}
+	}
+
+	fn UpdateShdr(&mut self, _ctx: *mut Box<Context>) {
+		self.Shdr.Size = OutputPlt::HEADER_SIZE + self.Symbols.len() * OutputPlt::ENTRY_SIZE;
+	}
+
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+}
+
+impl Chunker for OutputRelaPlt {
+	fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let gotplt = ctx.GotPlt.Shdr.Addr;
+		let buf = &mut ctx.Buf[self.Shdr.Offset..];
+		for (i, sym) in ctx.Plt.Symbols.iter().enumerate() {
+			let out = Rela {
+				Offset:	gotplt + (OutputGotPlt::HEADER_SIZE + i * 8) as u64,
+				Type:	abi::R_RISCV_JUMP_SLOT,
+				Sym:	sym.borrow().DynsymIdx.unwrap() as u32,
+				Addend:	0,
+			};
+			Write::<Rela>(&mut buf[i * self.Shdr.EntSize..], &out);
+		}
+	}
+
+	fn UpdateShdr(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		self.Shdr.Size = ctx.Plt.Symbols.len() * self.Shdr.EntSize;
+		self.Shdr.Link = ctx.Dynsym.Shndx as u32;
+		self.Shdr.Info = ctx.GotPlt.Shndx as u32;
+	}
+
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+}
+
+impl Chunker for OutputIplt {
+	/// the same as the PLT entries, except that the slots are in .got
+	fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let iplt = self.Shdr.Addr;
+		let slots: Vec<u64> = self.Symbols.iter().map(|sym| sym.borrow().GetGotAddr(ctx)).collect();
+		let buf = &mut ctx.Buf[self.Shdr.Offset..];
+
+		for (i, slot) in slots.iter().enumerate() {
+			let offset = i * OutputIplt::ENTRY_SIZE;
+			let entry = &mut buf[offset..];
+			for (j, insn) in PLT_ENTRY.iter().enumerate() {
+				Write::<u32>(&mut entry[j * 4..], insn);
+			}
+			let val = slot.wrapping_sub(iplt + offset as u64) as u32;
+			writeUtype(entry, val);
+			writeItype(&mut entry[4..], val);
+		}
+	}
+
+	fn UpdateShdr(&mut self, _ctx: *mut Box<Context>) {
+		self.Shdr.Size = self.Symbols.len() * OutputIplt::ENTRY_SIZE;
+	}
+
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+}
+
+impl Chunker for OutputRelaIplt {
+	fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let entries = OutputIplt::GetEntries(ctx);
+		let buf = &mut ctx.Buf[self.Shdr.Offset..];
+		for (i, rela) in entries.iter().enumerate() {
+			Write::<Rela>(&mut buf[i * self.Shdr.EntSize..], rela);
+		}
+	}
+
+	fn UpdateShdr(&mut self, ctx: *mut Box<Context>) {
+		self.Shdr.Size = ptr2ref(ctx).Iplt.Symbols.len() * self.Shdr.EntSize;
+	}
+
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+}
 
+impl Chunker for OutputEhFrameHdr {
+	/// addresses in the table are relative to the start of .eh_frame_hdr
+	fn CopyBuf(&mut self, ctx: *mut Box<Context>) {
+		let ctx = ptr2ref(ctx);
+		let hdr = self.Shdr.Addr;
+		let fdes = CollectFdes(ctx);
+		let ehframe = EhFrameSections(ctx)[0].borrow().OutputSection.borrow().Shdr.Addr;
+		let buf = &mut ctx.Buf[self.Shdr.Offset..];
+
+		buf[0] = 1;
+		buf[1] = DW_EH_PE_PCREL | DW_EH_PE_SDATA4;
+		buf[2] = DW_EH_PE_UDATA4;
+		buf[3] = DW_EH_PE_DATAREL | DW_EH_PE_SDATA4;
+		Write::<u32>(&mut buf[4..], &(ehframe.wrapping_sub(hdr + 4) as u32));
+		Write::<u32>(&mut buf[8..], &(fdes.len() as u32));
+		for (i, (pc, fde)) in fdes.iter().enumerate() {
+			let entry = &mut buf[OutputEhFrameHdr::HEADER_SIZE + i * OutputEhFrameHdr::ENTRY_SIZE..];
+			Write::<u32>(entry, &(pc.wrapping_sub(hdr) as u32));
+			Write::<u32>(&mut entry[4..], &(fde.wrapping_sub(hdr) as u32));
+		}
+	}
+
+	fn UpdateShdr(&mut self, ctx: *mut Box<Context>) {
+		self.Shdr.Size = OutputEhFrameHdr::HEADER_SIZE + CollectFdes(ptr2ref(ctx)).len() * OutputEhFrameHdr::ENTRY_SIZE;
+	}
+
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
 }
+
+impl Chunker for OutputDynbss {
+	fn CopyBuf(&mut self, _ctx: *mut Box<Context>) {/* nobits */}
+	fn UpdateShdr(&mut self, _ctx: *mut Box<Context>) {/* done by `ScanRelocations` */}
+	fn GetName(&self) -> &String { self.Chunk.GetName() }
+	fn GetShdr(&mut self) -> &mut Shdr { self.Chunk.GetShdr() }
+	fn GetShndx(&self) -> usize { self.Chunk.GetShndx() }
+	fn SetShndx(&mut self, shndx: usize) { self.Chunk.SetShndx(shndx) }
+}
+
+/// t1 holds the address after the PLT entry, and t3 the address of the
+/// header(the initial value of the .got.plt slot). the resolver takes the
+/// offset of the slot in t1, and the link map in t0
+const PLT_HEADER: [u32; 8] = [
+	0x00000397, // auipc  t2, %pcrel_hi(.got.plt)
+	0x41c30333, // sub    t1, t1, t3
+	0x0003be03, // ld     t3, %pcrel_lo(1b)(t2)    # the resolver
+	0xfd430313, // addi   t1, t1, -(32 + 12)
+	0x00038293, // addi   t0, t2, %pcrel_lo(1b)    # &.got.plt
+	0x00135313, // srli   t1, t1, 1
+	0x0082b283, // ld     t0, 8(t0)                # the link map
+	0x000e0067, // jr     t3
+];
+
+// pointer encodings of .eh_frame_hdr
+const DW_EH_PE_UDATA4: u8 = 0x03;
+const DW_EH_PE_SDATA4: u8 = 0x0b;
+const DW_EH_PE_PCREL: u8 = 0x10;
+const DW_EH_PE_DATAREL: u8 = 0x30;
+
+const PLT_ENTRY: [u32; 4] = [
+	0x00000e17, // auipc  t3, %pcrel_hi(function@.got.plt)
+	0x000e3e03, // ld     t3, %pcrel_lo(1b)(t3)
+	0x000e0367, // jalr   t1, t3
+	0x00100013, // nop
+];
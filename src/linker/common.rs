@@ -3,7 +3,7 @@
 pub use std::rc::Rc;
 pub use std::cell::RefCell;
 pub use std::ops::{Deref, DerefMut};
-pub use std::collections::BTreeMap;
+pub use std::collections::{BTreeMap, BTreeSet};
 
 pub use super::objectfile::Objectfile;
 pub use super::context::Context;
@@ -1,14 +1,84 @@
 //! useful informations collected and will be used during linking
 use super::common::*;
-use super::elf::{MachineType, Sym};
+use super::elf::{MachineType, Sym, IMAGE_BASE};
 use super::symbol::Symbol;
-use super::output::{OutputEhdr, OutputShdr, OutputSection, MergedSection, OutputPhdr};
+use super::script::LinkerScript;
+use super::output::{OutputEhdr, OutputShdr, OutputSection, MergedSection, OutputPhdr, OutputShstrtab, OutputSymtab, OutputStrtab, OutputDynamic, OutputRelaDyn,
+    OutputInterp, OutputDynsym, OutputDynstr, OutputHash, OutputGnuHash, OutputGot, OutputGotPlt, OutputPlt, OutputRelaPlt, OutputDynbss,
+    OutputIplt, OutputRelaIplt, OutputEhFrameHdr};
+
+/// `--unresolved-symbols`
+#[derive(Default, PartialEq)]
+pub enum UnresolvedSymbols {
+    #[default]
+    ReportAll,
+    IgnoreAll,
+    IgnoreInObjectFiles,
+}
+
+/// `--icf`
+#[derive(Default, PartialEq)]
+pub enum Icf {
+    #[default]
+    None,
+    All,
+    /// only sections whose address is never taken
+    Safe,
+}
+
+/// `--hash-style`
+#[derive(Default, PartialEq)]
+pub enum HashStyle {
+    Sysv,
+    Gnu,
+    #[default]
+    Both,
+}
 
 #[derive(Default)]
 pub struct ContextArgs {
     pub Output:         String,
     pub Emulation:      MachineType,
     pub LIbraryPaths:   Vec<String>,
+    /// a symbol name or an address, set by `-e`
+    pub Entry:          String,
+    /// `-s`. no .symtab/.strtab and debug sections
+    pub StripAll:       bool,
+    /// `-S`. no debug sections
+    pub StripDebug:     bool,
+    pub UnresolvedSymbols:      UnresolvedSymbols,
+    /// `--warn-unresolved-symbols`. report them as warnings instead of errors
+    pub WarnUnresolvedSymbols:  bool,
+    /// `--allow-multiple-definition` or `-z muldefs`. the first definition wins
+    pub AllowMultipleDefinition:    bool,
+    /// `--no-relax`
+    pub NoRelax:        bool,
+    /// `-u`. symbols that must be kept even if no one references them
+    pub Undefined:      Vec<String>,
+    /// `--gc-sections`
+    pub GcSections:     bool,
+    /// `--print-gc-sections`. list the sections removed by gc
+    pub PrintGcSections:    bool,
+    pub Icf:            Icf,
+    /// `--print-icf-sections`
+    pub PrintIcfSections:   bool,
+    /// `--image-base`. where the image starts
+    pub ImageBase:      u64,
+    /// `--section-start`, `-Ttext`, `-Tdata` and `-Tbss`. output section name -> address
+    pub SectionStart:   BTreeMap<String, u64>,
+    /// `-pie` or `-static-pie`. the output is ET_DYN, and relocates itself at startup
+    pub Pie:            bool,
+    /// `-static` or `-Bstatic`. no shared libraries for the inputs that follow
+    pub Static:         bool,
+    /// `--as-needed`, for the inputs that follow
+    pub AsNeeded:       bool,
+    /// `-dynamic-linker`. goes to .interp. empty with `--no-dynamic-linker`
+    pub DynamicLinker:  String,
+    /// `-E` or `--export-dynamic`. put all the global symbols into .dynsym
+    pub ExportDynamic:  bool,
+    pub HashStyle:      HashStyle,
+    /// `--eh-frame-hdr`. create .eh_frame_hdr and PT_GNU_EH_FRAME
+    pub EhFrameHdr:     bool,
 }
 
 #[derive(Default)]
@@ -23,7 +93,26 @@ pub struct Context {
     pub Ehdr:           Box<OutputEhdr>,
     pub Shdr:           Box<OutputShdr>,
     pub Phdr:           Box<OutputPhdr>,
+    pub Shstrtab:       Box<OutputShstrtab>,
+    pub Symtab:         Box<OutputSymtab>,
+    pub Strtab:         Box<OutputStrtab>,
+    pub Dynamic:        Box<OutputDynamic>,
+    pub RelaDyn:        Box<OutputRelaDyn>,
+    pub Interp:         Box<OutputInterp>,
+    pub Dynsym:         Box<OutputDynsym>,
+    pub Dynstr:         Box<OutputDynstr>,
+    pub Hash:           Box<OutputHash>,
+    pub GnuHash:        Box<OutputGnuHash>,
+    pub Got:            Box<OutputGot>,
+    pub GotPlt:         Box<OutputGotPlt>,
+    pub Plt:            Box<OutputPlt>,
+    pub RelaPlt:        Box<OutputRelaPlt>,
+    pub Dynbss:         Box<OutputDynbss>,
+    pub Iplt:           Box<OutputIplt>,
+    pub RelaIplt:       Box<OutputRelaIplt>,
+    pub EhFrameHdr:     Box<OutputEhFrameHdr>,
     pub TpAddr:         u64,    // thread local pointer
+    pub GpAddr:         u64,    // the value of __global_pointer$
 
     pub OutputSections: Vec<Rc<RefCell<OutputSection>>>,
     /// each chunk in this vector will finally be written into the target file.
@@ -43,6 +132,10 @@ pub struct Context {
     // this internalobj will also exist in ctx.objs
     pub InternalObj:    Rc<RefCell<Objectfile>>,
     pub InternalEsyms:  Vec<Rc<Sym>>,
+    /// COMDAT group signature -> the file whose copy is kept
+    pub ComdatGroups:   BTreeMap<String, Rc<RefCell<Objectfile>>>,
+    /// `-T`
+    pub Script:         Option<LinkerScript>,
 }
 
 impl Context {
@@ -52,8 +145,27 @@ impl Context {
                 Output: "a.out".into(),
                 Emulation: MachineType::MachineTypeNone,
                 LIbraryPaths: vec![],
+                Entry: "_start".into(),
+                ImageBase: IMAGE_BASE as u64,
+                DynamicLinker: "/lib/ld-linux-riscv64-lp64d.so.1".into(),
+                ..Default::default()
             },
             ..Default::default()
         })
     }
+
+    /// shared libraries are linked in, so the output is loaded by the dynamic linker
+    pub fn IsDynamic(&self) -> bool {
+        self.Objs.iter().any(|obj| obj.borrow().IsDso())
+    }
+
+    /// a .dynamic is needed, either for the dynamic linker, or for a static PIE to relocate itself
+    pub fn NeedsDynamic(&self) -> bool {
+        self.Args.Pie || self.IsDynamic()
+    }
+
+    /// the output names its dynamic linker in .interp
+    pub fn NeedsInterp(&self) -> bool {
+        self.IsDynamic() && !self.Args.DynamicLinker.is_empty()
+    }
 }
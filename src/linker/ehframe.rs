@@ -0,0 +1,202 @@
+//! .eh_frame is a sequence of records. CIEs hold what the functions of a file
+//! have in common, and each FDE describes the unwinding of one function and
+//! refers back to its CIE. they are handled one by one, instead of copying
+//! the whole section, so that the FDEs of removed functions can be dropped
+//! and the CIEs repeated in every object file are written only once
+
+use std::ops::Range;
+use super::common::*;
+use super::elf::Rela;
+use super::inputsections::InputSection;
+
+/// the records of an .eh_frame input section
+#[derive(Default, Debug)]
+pub struct EhFrame {
+	pub Cies:		Vec<CieRecord>,
+	pub Fdes:		Vec<FdeRecord>,
+	/// the section ends with a zero length word(crtend.o has one)
+	pub Terminator:	bool,
+}
+
+#[derive(Default, Debug)]
+pub struct CieRecord {
+	/// offset in the input section, and the size including the length field
+	pub Offset:			usize,
+	pub Size:			usize,
+	/// indices into `Rels` of the section
+	pub Rels:			Range<usize>,
+	/// the identical CIE written instead of this one, (section, index)
+	pub Leader:			Option<(Rc<RefCell<InputSection>>, usize)>,
+	/// offset in the output of the section
+	pub OutputOffset:	usize,
+}
+
+#[derive(Default, Debug)]
+pub struct FdeRecord {
+	pub Offset:			usize,
+	pub Size:			usize,
+	pub Rels:			Range<usize>,
+	/// index into `Cies`
+	pub Cie:			usize,
+	/// the section of the function, which the first relocation points to
+	pub Target:			Option<Rc<RefCell<InputSection>>>,
+	pub IsAlive:		bool,
+	pub OutputOffset:	usize,
+}
+
+/// identical CIEs have the same contents, and relocations to the same symbols.
+/// (contents, [(offset, type, addend, symbol)])
+pub type CieKey = (Vec<u8>, Vec<(usize, u32, i64, usize)>);
+
+impl InputSection {
+	/// records are split by their length fields. the relocations
+	/// are sorted by offset, so each record takes a range of them
+	pub fn SplitEhFrame(&mut self, file: &Objectfile) {
+		let mut eh = EhFrame::default();
+		let data = self.Contents.GetSlice();
+		let (mut offset, mut relIdx) = (0, 0);
+		while offset < data.len() {
+			let size = Read::<u32>(&data[offset..]) as usize;
+			if size == 0 {
+				eh.Terminator = true;
+				break;
+			}
+			if size == u32::MAX as usize {
+				error!("{}: 64-bit .eh_frame records are not supported", file.Name());
+			}
+
+			let end = offset + 4 + size;
+			if end > data.len() {
+				error!("{}: .eh_frame record at {:#x} is out of range", file.Name(), offset);
+			}
+
+			let begin = relIdx;
+			while relIdx < self.Rels.len() && (self.Rels[relIdx].Offset as usize) < end {
+				if (self.Rels[relIdx].Offset as usize) < offset {
+					error!("{}: relocations of .eh_frame are not sorted", file.Name());
+				}
+				relIdx += 1;
+			}
+
+			// the CIE pointer of an FDE is the distance back to its CIE
+			let id = Read::<u32>(&data[offset + 4..]) as usize;
+			if id == 0 {
+				eh.Cies.push(CieRecord { Offset: offset, Size: end - offset, Rels: begin..relIdx, ..Default::default() });
+				offset = end;
+				continue;
+			}
+
+			let cie = match eh.Cies.iter().position(|c| c.Offset + id == offset + 4) {
+				Some(cie) => cie,
+				None => {
+					error!("{}: bad CIE pointer in .eh_frame at {:#x}", file.Name(), offset);
+				}
+			};
+			let target = match self.Rels.get(begin) {
+				Some(rel) if begin < relIdx && rel.Offset as usize == offset + 8 => GetTargetSection(file, rel),
+				_ => None
+			};
+			eh.Fdes.push(FdeRecord { Offset: offset, Size: end - offset, Rels: begin..relIdx, Cie: cie, Target: target, ..Default::default() });
+			offset = end;
+		}
+		self.EhFrame = Some(eh);
+	}
+
+	pub fn CieKeys(&self) -> Vec<CieKey> {
+		let file = self.File.borrow();
+		let data = self.Contents.GetSlice();
+		self.EhFrame.as_ref().unwrap().Cies.iter().map(|cie| {
+			let rels = self.Rels[cie.Rels.clone()].iter().map(|rel| {
+				let sym = file.Symbols.get(&(rel.Sym as usize)).unwrap();
+				(rel.Offset as usize - cie.Offset, rel.Type, rel.Addend, sym.as_ptr() as usize)
+			}).collect();
+			(data[cie.Offset..cie.Offset + cie.Size].to_vec(), rels)
+		}).collect()
+	}
+
+	/// the CIEs kept and the live FDEs, with their CIE pointers recomputed
+	pub fn WriteEhFrame(&self, ctx: &Context, buf: &mut [u8]) {
+		let eh = self.EhFrame.as_ref().unwrap();
+		let data = self.Contents.GetSlice();
+		for cie in eh.Cies.iter().filter(|cie| cie.Leader.is_none()) {
+			buf[cie.OutputOffset..cie.OutputOffset + cie.Size].copy_from_slice(&data[cie.Offset..cie.Offset + cie.Size]);
+			self.ApplyEhFrameRelocs(ctx, buf, cie.Rels.clone(), cie.Offset, cie.OutputOffset);
+		}
+
+		for fde in eh.Fdes.iter().filter(|fde| fde.IsAlive) {
+			buf[fde.OutputOffset..fde.OutputOffset + fde.Size].copy_from_slice(&data[fde.Offset..fde.Offset + fde.Size]);
+			self.ApplyEhFrameRelocs(ctx, buf, fde.Rels.clone(), fde.Offset, fde.OutputOffset);
+			let P = self.GetAddr() + fde.OutputOffset as u64 + 4;
+			Write::<u32>(&mut buf[fde.OutputOffset + 4..], &(P.wrapping_sub(self.GetCieAddr(fde.Cie)) as u32));
+		}
+
+		if eh.Terminator {
+			Write::<u32>(&mut buf[self.ShSize - 4..], &0);
+		}
+	}
+
+	fn ApplyEhFrameRelocs(&self, ctx: &Context, buf: &mut [u8], rels: Range<usize>, offset: usize, outputOffset: usize) {
+		let file = self.File.borrow();
+		for idx in rels {
+			let rel = &self.Rels[idx];
+			if rel.Type == abi::R_RISCV_NONE {
+				continue;
+			}
+
+			let sym = file.Symbols.get(&(rel.Sym as usize)).unwrap().borrow();
+			let loc = rel.Offset as usize - offset + outputOffset;
+			let SA = self.GetSA(idx, rel, &sym);
+			let P = self.GetAddr() + loc as u64;
+			self.ApplyReloc(ctx, rel, &sym, &mut buf[loc..], SA, P);
+		}
+	}
+
+	fn GetCieAddr(&self, idx: usize) -> u64 {
+		let cie = &self.EhFrame.as_ref().unwrap().Cies[idx];
+		match &cie.Leader {
+			Some((isec, idx)) => {
+				let isec = isec.borrow();
+				isec.GetAddr() + isec.EhFrame.as_ref().unwrap().Cies[*idx].OutputOffset as u64
+			},
+			None => self.GetAddr() + cie.OutputOffset as u64
+		}
+	}
+
+	/// (address of the function, address of the FDE) of the live FDEs
+	fn GetFdeEntries(&self) -> Vec<(u64, u64)> {
+		let file = self.File.borrow();
+		self.EhFrame.as_ref().unwrap().Fdes.iter().filter(|fde| fde.IsAlive).map(|fde| {
+			let rel = &self.Rels[fde.Rels.start];
+			let sym = file.Symbols.get(&(rel.Sym as usize)).unwrap().borrow();
+			(self.GetSA(fde.Rels.start, rel, &sym), self.GetAddr() + fde.OutputOffset as u64)
+		}).collect()
+	}
+}
+
+/// the section of this file a relocation refers to. for global symbols it's
+/// where this file defines them, even if another definition is chosen
+fn GetTargetSection(file: &Objectfile, rel: &Rela) -> Option<Rc<RefCell<InputSection>>> {
+	let esym = &file.ElfSyms[rel.Sym as usize];
+	if esym.IsUndef() || esym.IsAbs() || esym.IsCommon() {
+		return None;
+	}
+	file.Sections.get(file.GetShndx(esym, rel.Sym as usize)).cloned().flatten()
+}
+
+/// the .eh_frame sections going to the output
+pub fn EhFrameSections(ctx: &Context) -> Vec<Rc<RefCell<InputSection>>> {
+	ctx.Objs.iter().flat_map(|file| {
+		file.borrow().Sections.iter().flatten()
+			.filter(|isec| isec.borrow().IsAlive && isec.borrow().EhFrame.is_some())
+			.cloned().collect::<Vec<_>>()
+	}).collect()
+}
+
+/// the binary search table of .eh_frame_hdr, sorted by the function address
+pub fn CollectFdes(ctx: &Context) -> Vec<(u64, u64)> {
+	let mut fdes: Vec<(u64, u64)> = EhFrameSections(ctx).iter()
+		.flat_map(|isec| isec.borrow().GetFdeEntries())
+		.collect();
+	fdes.sort();
+	fdes
+}
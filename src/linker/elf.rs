@@ -6,6 +6,8 @@ pub const PHDR_SIZE: usize = core::mem::size_of::<Phdr>();
 pub const IMAGE_BASE: usize = 0x200000;
 pub const MAGIC: &[u8] = b"\x7fELF";
 pub const PAGESIZE: u64 = 4096;
+/// the flag word of a SHT_GROUP section
+pub const GRP_COMDAT: u32 = 1;
 
 pub fn checkMagic(s: &[u8]) -> bool {
     s.starts_with(MAGIC)
@@ -162,13 +164,39 @@ pub struct Sym {
 	pub Size:       u64,
 }
 
+/// Relocation entry with an explicit addend(SHT_RELA)
+#[derive(Debug, Default, Clone)]
+#[repr(C)]
+pub struct Rela {
+    /// the location to be patched. for relocatable files, it's
+    /// an offset from the beginning of the target section
+    pub Offset:     u64,
+    /// r_info is splited into two parts. little endian, type comes first
+    pub Type:       u32,
+    /// index of the referenced symbol in the object's symtab
+    pub Sym:        u32,
+    /// constant added to the symbol's value
+    pub Addend:     i64,
+}
+
+/// an entry of the .dynamic section
+#[derive(Debug, Default, Clone)]
+#[repr(C)]
+pub struct Dyn {
+    pub Tag:        i64,
+    pub Val:        u64,
+}
+
 #[derive(PartialEq, Default, Clone, Debug)]
 pub enum FileType{
 	#[default]
-	FileTypeUnknown,
-	FileTypeEmpty,
-	FileTypeObject,
-	FileTypeArchive,
+	Unknown,
+	Empty,
+	Object,
+	Archive,
+	SharedObject,
+	/// a text file, taken as a linker script. e.g. libc.so
+	Script,
 }
 
 #[derive(Debug, PartialEq, Default)]
@@ -208,6 +236,17 @@ impl Sym {
     pub fn Type(&self) -> u8 {
         self.Info & 0b1111
     }
+    /// the high 4 bits of `Info`. STB_LOCAL, STB_GLOBAL, STB_WEAK...
+    pub fn Bind(&self) -> u8 {
+        self.Info >> 4
+    }
+    pub fn IsWeak(&self) -> bool {
+        self.Bind() == abi::STB_WEAK
+    }
+    /// the low 2 bits of `Other`. STV_DEFAULT, STV_HIDDEN...
+    pub fn Visibility(&self) -> u8 {
+        self.Other & 0b11
+    }
 }
 
 pub fn GetMachineType(file: &File) -> MachineType {
@@ -215,7 +254,7 @@ pub fn GetMachineType(file: &File) -> MachineType {
 	let Contents = &file.Contents;
 	let machine = Read::<u16>(&Contents[18..]);
 	match ft {
-		FileType::FileTypeObject => {
+		FileType::Object | FileType::SharedObject => {
 			if machine == abi::EM_RISCV {
 				return match Contents[4]{
 					abi::ELFCLASS64 => MachineType::MachineTypeRISCV64,
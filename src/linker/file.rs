@@ -4,6 +4,7 @@ use super::elf::{Sym, FileType};
 use super::elf::checkMagic;
 use super::archive::ReadArchiveMembers;
 use super::symbol::Symbol;
+use super::script::LinkerScript;
 use super::common::*;
 
 #[derive(Default, Clone,Debug)]
@@ -51,19 +52,23 @@ impl File {
         };
         let ft: FileType;
         if Contents.len() == 0 {
-            ft = FileType::FileTypeEmpty;
+            ft = FileType::Empty;
         }
         else if checkMagic(&Contents) {
             ft = match Read::<u16>(&Contents[16..]) {
-                abi::ET_REL => FileType::FileTypeObject,
-            _ =>    FileType::FileTypeUnknown
+                abi::ET_REL => FileType::Object,
+                abi::ET_DYN => FileType::SharedObject,
+            _ =>    FileType::Unknown
             };
         }
         else if Contents.starts_with(super::archive::AR_IDENT) {
-            ft = FileType::FileTypeArchive;
+            ft = FileType::Archive;
+        }
+        else if std::str::from_utf8(&Contents).is_ok() {
+            ft = FileType::Script;
         }
         else{
-            ft = FileType::FileTypeUnknown;
+            ft = FileType::Unknown;
         }
 
         Rc::new(
@@ -77,6 +82,16 @@ impl File {
     }
 }
 
+impl File {
+    /// `foo.o`, or `libfoo.a(foo.o)` for archive members
+    pub fn DisplayName(&self) -> String {
+        match &self.Parent {
+            Some(parent) => format!("{}({})", parent.Name, self.Name),
+            None => self.Name.clone()
+        }
+    }
+}
+
 impl InputFile {
     pub fn new(file: Rc<File>) -> Box<Self> {
         let name = &file.Name;
@@ -160,31 +175,62 @@ impl InputFile {
     }
 }
 
-/// collect all the objects into ctx.objs, from input *.o or inside archives
+/// collect all the objects into ctx.objs, from input *.o, shared libraries or inside archives.
+/// `-Bstatic`, `-Bdynamic`, `--as-needed` and `--no-as-needed` are kept in
+/// `remaining` by `parseArgs`, as they apply to the files after them
 pub fn ReadInputFiles(ctx: &mut Context, remaining: Vec<String>) {
     for arg in remaining {
-        if let Some(arg) = arg.strip_prefix("-l") {
-            ReadFile(ctx, FindLibrary(ctx, arg).unwrap());
-        }
-        else {
-            ReadFile(ctx, File::new(&arg, vec![], None));
+        match arg.as_str() {
+            "-Bstatic" => ctx.Args.Static = true,
+            "-Bdynamic" => ctx.Args.Static = false,
+            "--as-needed" => ctx.Args.AsNeeded = true,
+            "--no-as-needed" => ctx.Args.AsNeeded = false,
+            _ => ReadInput(ctx, &arg),
         }
     }
 }
 
+/// a file name, or `-lfoo`
+fn ReadInput(ctx: &mut Context, arg: &str) {
+    if let Some(arg) = arg.strip_prefix("-l") {
+        ReadFile(ctx, FindLibrary(ctx, arg).unwrap());
+    }
+    else {
+        ReadFile(ctx, File::new(arg, vec![], None));
+    }
+}
+
 pub fn ReadFile(ctx: &mut Context, file: Rc<File>) {
     match file.Type {
-        FileType::FileTypeObject => {
+        FileType::Object => {
             let obj = Objectfile::new(ctx, file, true);
             ctx.Objs.push(obj);
         },
-        FileType::FileTypeArchive => {
+        FileType::Archive => {
             for child in ReadArchiveMembers(file) {
-                assert!(child.Type == FileType::FileTypeObject);
+                assert!(child.Type == FileType::Object);
                 let obj = Objectfile::new(ctx, child, false);
                 ctx.Objs.push(obj);
             }
         },
+        FileType::SharedObject => {
+            if ctx.Args.Static {
+                error!("{}: attempted static link of dynamic object", file.Name);
+            }
+            let dso = Objectfile::new(ctx, file, true);
+            dso.borrow_mut().AsNeeded = ctx.Args.AsNeeded;
+            ctx.Objs.push(dso);
+        },
+        // only INPUT and GROUP are meaningful in a script found among the inputs
+        FileType::Script => {
+            let script = LinkerScript::Parse(&file.Name, &String::from_utf8_lossy(&file.Contents));
+            let asNeeded = ctx.Args.AsNeeded;
+            for (name, needed) in &script.InputFiles {
+                ctx.Args.AsNeeded = asNeeded || *needed;
+                ReadInput(ctx, name);
+            }
+            ctx.Args.AsNeeded = asNeeded;
+        },
         _ => {
             error!("unknown file type!");
         }
@@ -199,10 +245,17 @@ pub fn OpenLibrary(path: &str) -> Option<Rc<File>> {
 	}
 }
 
+/// shared libraries are preferred in each directory, unless `-Bstatic`
 pub fn FindLibrary(ctx: &Context, name: &str) -> Option<Rc<File>> {
 	for dir in &ctx.Args.LIbraryPaths {
-		let stem = dir.to_owned() + "/lib" + name + ".a";
-		let f = OpenLibrary(&stem);
+		let stem = dir.to_owned() + "/lib" + name;
+		if !ctx.Args.Static {
+			let f = OpenLibrary(&(stem.clone() + ".so"));
+			if f.is_some() {
+				return f;
+			}
+		}
+		let f = OpenLibrary(&(stem + ".a"));
 		if f.is_some() {
 			return f;
 		}
@@ -1,9 +1,11 @@
 //! a further abstractions for elf sections, making it easier to use
 
-use crate::linker::output::GetOutputSection;
+use crate::linker::output::{GetOutputSection, GetOutputName, GetScriptOutputSection};
 
-use super::elf::ElfGetName;
+use super::elf::{ElfGetName, Rela};
 use super::output::{OutputSection, MergedSection};
+use super::relax::Relaxation;
+use super::ehframe::EhFrame;
 
 use super::common::*;
 
@@ -26,6 +28,22 @@ pub struct InputSection {
 	pub Offset:		usize,
 	/// multiple inputsecs could be mapped to the same outputsec
 	pub OutputSection:	Rc<RefCell<OutputSection>>,
+	/// relocations from the SHT_RELA section whose `Info` points to us
+	pub Rels:		Vec<Rela>,
+	/// relocations that refer to a mergeable section by its section symbol.
+	/// key is the index into `Rels`, value is (fragment, offset in fragment)
+	pub RelFragments:	BTreeMap<usize, (Rc<RefCell<SectionFragment>>, u64)>,
+	/// bytes deleted by relaxation, sorted by offset.
+	/// (offset in `Contents`, total bytes deleted up to and including this one)
+	pub Deltas:		Vec<(u64, u64)>,
+	/// key is the index into `Rels`
+	pub Relaxations:	BTreeMap<usize, Relaxation>,
+	/// index of the input section description in the linker script which matched us
+	pub ScriptOrder:	Option<usize>,
+	/// KEEP in the linker script. never removed by gc
+	pub Keep:		bool,
+	/// the CIEs and FDEs, if this is an .eh_frame
+	pub EhFrame:	Option<EhFrame>,
 }
 
 #[derive(Default,Debug, Clone)]
@@ -69,7 +87,23 @@ impl InputSection {
 			0 => 0,
 			_ => shdr.AddrAlign.trailing_zeros() as u8
 		};
-		s.OutputSection = GetOutputSection(ctx, name, shdr.Type, shdr.Flags);
+
+		let rule = ctx.Script.as_ref().and_then(|script| {
+			script.MatchInputSection(&ptr.File, &name).map(|i| (i, script.Inputs[i].clone()))
+		});
+		match rule {
+			Some((i, rule)) => {
+				s.ScriptOrder = Some(i);
+				s.Keep = rule.Keep;
+				if rule.Output == "/DISCARD/" {
+					s.IsAlive = false;
+				}
+				else {
+					s.OutputSection = GetScriptOutputSection(ctx, rule.Output, shdr.Type, shdr.Flags, rule.NoLoad);
+				}
+			},
+			None => s.OutputSection = GetOutputSection(ctx, name, shdr.Type, shdr.Flags),
+		}
 		s.ToRcRefcell()
 	}
 
@@ -87,16 +121,39 @@ impl InputSection {
 		ElfGetName(&self.File.borrow().Shstrtab.GetSlice(), self.Shdr().Name as usize)
 	}
 
-	pub fn WriteTo(&mut self, buf: &mut [u8]) {
-		if self.Shdr().Type != abi::SHT_NOBITS && self.ShSize != 0 {
+	/// address of this section in the output image
+	pub fn GetAddr(&self) -> u64 {
+		self.OutputSection.borrow().Shdr.Addr + self.Offset as u64
+	}
+
+	pub fn WriteTo(&self, ctx: &Context, buf: &mut [u8]) {
+		if self.EhFrame.is_some() {
+			self.WriteEhFrame(ctx, buf);
+		}
+		else if self.Shdr().Type != abi::SHT_NOBITS && self.ShSize != 0 {
 			self.CopyContents(buf);
+			self.ApplyRelocs(ctx, buf);
 		}
 	}
 
 	// mark
-	fn CopyContents(&mut self, buf: &mut [u8]) {
+	fn CopyContents(&self, buf: &mut [u8]) {
 		let slice = self.Contents.GetSlice();
-		buf[..self.Contents.1].copy_from_slice(slice);
+		if self.Deltas.is_empty() {
+			buf[..self.Contents.1].copy_from_slice(slice);
+			return;
+		}
+
+		// skip the bytes deleted by relaxation
+		let (mut src, mut dst, mut prev) = (0, 0, 0);
+		for (offset, delta) in &self.Deltas {
+			let offset = *offset as usize;
+			buf[dst..dst + offset - src].copy_from_slice(&slice[src..offset]);
+			dst += offset - src;
+			src = offset + (delta - prev) as usize;
+			prev = *delta;
+		}
+		buf[dst..dst + slice.len() - src].copy_from_slice(&slice[src..]);
 	}
 }
 
@@ -128,6 +185,11 @@ impl SectionFragment {
 			..Default::default()
 		}.ToRcRefcell()
 	}
+
+	/// address of this fragment in the output image
+	pub fn GetAddr(&self) -> u64 {
+		self.OutputSection.borrow().Shdr.Addr + self.Offset as u64
+	}
 }
 
 /// drop input section's mutable borrow before calling this fn...
@@ -136,7 +198,11 @@ pub fn SplitSection(ctx: &mut Context, isec: Rc<RefCell<InputSection>>) -> Box<M
 
 	let isec = isec.borrow();
 	let shdr = isec.Shdr();
-	m.Parent = MergedSection::GetInstance(ctx, &isec.Name(), shdr.Type, shdr.Flags);
+	let name = match (&ctx.Script, isec.ScriptOrder) {
+		(Some(script), Some(i)) => script.Inputs[i].Output.clone(),
+		_ => GetOutputName(&isec.Name(), shdr.Flags)
+	};
+	m.Parent = MergedSection::GetInstance(ctx, &name, shdr.Type, shdr.Flags);
 	m.P2Align = isec.P2Align;
 
 	let mut data = isec.Contents.GetSlice();
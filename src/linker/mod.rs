@@ -4,9 +4,13 @@ pub mod context;
 pub mod archive;
 pub mod passes;
 pub mod objectfile;
+pub mod script;
 
 pub mod output;
 mod inputsections;
 mod symbol;
 mod common;
-mod chunker;
\ No newline at end of file
+mod chunker;
+mod relocation;
+mod relax;
+mod ehframe;
\ No newline at end of file
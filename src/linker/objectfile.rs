@@ -1,7 +1,7 @@
 use super::common::*;
 use elf::abi::*;
 use super::file::{InputFile, File};
-use super::elf::{CheckFileCompatibility, Sym, ElfGetName};
+use super::elf::{CheckFileCompatibility, Sym, Dyn, ElfGetName, Rela, FileType, GRP_COMDAT};
 use super::inputsections::{InputSection, MergeableSection, SplitSection};
 use super::symbol::Symbol;
 use super::output::MergedSection;
@@ -13,7 +13,17 @@ pub struct Objectfile {
     pub SymTabSec:          *const Shdr,
 	pub SymtabShndxSec:     Vec<u32>,
 	pub Sections:           Vec<Option<Rc<RefCell<InputSection>>>>,
-    pub MergeableSections:  Vec<Option<MergeableSection>>
+    pub MergeableSections:  Vec<Option<MergeableSection>>,
+    /// (signature, member section indices) of COMDAT groups
+    pub ComdatGroups:       Vec<(String, Vec<u32>)>,
+    /// for shared libraries. what goes to DT_NEEDED
+    pub Soname:             String,
+    /// `--as-needed`. DT_NEEDED only if some of its symbols are used
+    pub AsNeeded:           bool,
+    /// .gnu.version of a shared library, one for each symbol
+    pub VerSyms:            Vec<u16>,
+    /// DT_NEEDED of a shared library, the libraries it was linked against
+    pub Needed:             Vec<String>,
 }
 
 impl Default for Objectfile {
@@ -24,7 +34,12 @@ impl Default for Objectfile {
             inputFile:  Default::default(),
             Sections:   Default::default(),
             SymtabShndxSec: Default::default(),
-            MergeableSections:  Default::default()
+            MergeableSections:  Default::default(),
+            ComdatGroups:   Default::default(),
+            Soname:     Default::default(),
+            AsNeeded:   false,
+            VerSyms:    Default::default(),
+            Needed:     Default::default(),
         }
     }
 }
@@ -59,9 +74,17 @@ impl Objectfile {
         &self.Name
     }
 
+    pub fn IsDso(&self) -> bool {
+        self.File.Type == FileType::SharedObject
+    }
+
     pub fn Parse(obj: Rc<RefCell<Objectfile>>, ctx: &mut Context) {
         let mut o = obj.borrow_mut();
-        o.SymTabSec = o.FindSection(SHT_SYMTAB);
+        // a shared library only exports what's in .dynsym
+        o.SymTabSec = match o.IsDso() {
+            true => o.FindSection(SHT_DYNSYM),
+            false => o.FindSection(SHT_SYMTAB),
+        };
 
         //if let Some(symtab) = o.SymTabSec.clone() {
         if !o.SymTabSec.is_null() {
@@ -73,9 +96,54 @@ impl Objectfile {
         }
 
         drop(o);
+        if obj.borrow().IsDso() {
+            obj.borrow_mut().InitDso();
+            Objectfile::InitSymbols(&obj, ctx);
+            return;
+        }
         Objectfile::InitSections(&obj, ctx);
         Objectfile::InitSymbols(&obj, ctx);
         Objectfile::InitMergeableSections(obj.clone(), ctx);
+        obj.borrow().InitEhFrames();
+    }
+
+    /// none of the sections of a shared library goes to the output
+    fn InitDso(&mut self) {
+        let len = self.ElfSections.len();
+        self.Sections = vec![None; len];
+        self.MergeableSections = vec![Default::default(); len];
+
+        let versym = self.FindSection(SHT_GNU_VERSYM);
+        if !versym.is_null() {
+            self.VerSyms = ReadSlice::<u16>(self.GetBytesFromShdr(unsafe {&*versym}));
+        }
+
+        // without DT_SONAME, the library is known by its file name
+        let mut soname = std::path::Path::new(&self.Name).file_name().unwrap().to_string_lossy().into_owned();
+        let mut needed = vec![];
+        let dynamic = self.FindSection(SHT_DYNAMIC);
+        if !dynamic.is_null() {
+            let dynamic = unsafe {&*dynamic};
+            let strtab = self.GetBytesFromIdx(dynamic.Link as usize);
+            for d in ReadSlice::<Dyn>(self.GetBytesFromShdr(dynamic)) {
+                match d.Tag {
+                    DT_SONAME => soname = ElfGetName(strtab, d.Val as usize),
+                    DT_NEEDED => needed.push(ElfGetName(strtab, d.Val as usize)),
+                    _ => {}
+                }
+            }
+        }
+        self.Soname = soname;
+        self.Needed = needed;
+    }
+
+    /// a symbol of a shared library that can't be linked against, e.g. an
+    /// old version `foo@VER_1` which is not the default `foo@@VER_2`
+    pub fn IsHiddenVersion(&self, idx: usize) -> bool {
+        match self.VerSyms.get(idx) {
+            Some(ver) => *ver & VER_NDX_HIDDEN != 0 || *ver == VER_NDX_LOCAL,
+            None => false
+        }
     }
 
     fn InitSections(obj: &Rc<RefCell<Self>>, ctx: &mut Context) {
@@ -88,14 +156,20 @@ impl Objectfile {
             match shdr.Type {
                 // these massages are only used during linkding,
                 // no need to put them into output file
-                SHT_GROUP | SHT_SYMTAB | SHT_STRTAB | SHT_REL | SHT_RELA | SHT_NULL => {
+                SHT_SYMTAB | SHT_STRTAB | SHT_REL | SHT_RELA | SHT_NULL => {
                     continue;
                 },
+                SHT_GROUP => {
+                    obj.borrow_mut().ReadComdatGroup(shdr);
+                },
                 SHT_SYMTAB_SHNDX => {
                     obj.borrow_mut().FillUpSymtabShndxSec(shdr);
                 },
                 _ => {
                     let name = ElfGetName(&obj.borrow().Shstrtab.GetSlice(), shdr.Name as usize);
+                    if (ctx.Args.StripAll || ctx.Args.StripDebug) && name.starts_with(".debug") {
+                        continue;
+                    }
                     let sec = InputSection::new(ctx, name, obj.clone(), i);
                     // error. we should follow the index, or use a btreemap?
                     //obj.borrow_mut().Sections.push(sec);
@@ -103,6 +177,30 @@ impl Objectfile {
                 },
             }
         }
+
+        // attach relocations to the sections they apply to.
+        // this must be done after all the input sections are created
+        for i in 0..len {
+            let o = obj.borrow();
+            let shdr = &o.ElfSections[i];
+            if shdr.Type != SHT_RELA {
+                continue;
+            }
+
+            if let Some(target) = &o.Sections[shdr.Info as usize] {
+                target.borrow_mut().Rels = ReadSlice::<Rela>(o.GetBytesFromShdr(shdr));
+            }
+        }
+    }
+
+    /// .eh_frame is split into records, so that the ones for removed functions can be dropped
+    fn InitEhFrames(&self) {
+        for isec in self.Sections.iter().flatten() {
+            let mut isec = isec.borrow_mut();
+            if isec.IsAlive && isec.Name() == ".eh_frame" {
+                isec.SplitEhFrame(self);
+            }
+        }
     }
 
     // find out which sections are mergeable
@@ -125,6 +223,33 @@ impl Objectfile {
         }
     }
 
+    /// the contents is a flag word followed by the indices of the members.
+    /// the signature is the name of the symbol `Info` points to
+    fn ReadComdatGroup(&mut self, shdr: &Shdr) {
+        let words = ReadSlice::<u32>(self.GetBytesFromShdr(shdr));
+        if words.is_empty() || words[0] & GRP_COMDAT == 0 {
+            return;
+        }
+
+        let esym = &self.ElfSyms[shdr.Info as usize];
+        let signature = match esym.Type() {
+            STT_SECTION => ElfGetName(self.Shstrtab.GetSlice(), self.ElfSections[esym.Shndx as usize].Name as usize),
+            _ => ElfGetName(self.SymbolStrTab.GetSlice(), esym.Name as usize)
+        };
+        self.ComdatGroups.push((signature, words[1..].to_vec()));
+    }
+
+    /// throw away the members of a group whose copy is kept by another file
+    pub fn DiscardComdatGroup(&mut self, members: &Vec<u32>) {
+        for idx in members {
+            let idx = *idx as usize;
+            if let Some(isec) = self.Sections[idx].take() {
+                isec.borrow_mut().IsAlive = false;
+            }
+            self.MergeableSections[idx] = None;
+        }
+    }
+
     fn FillUpSymtabShndxSec(&mut self, shdr: &Shdr) {
         let bytes = InputFile::GetBytesFromShdr(&self, shdr);
         self.SymtabShndxSec = ReadSlice::<u32>(&bytes);
@@ -162,6 +287,7 @@ impl Objectfile {
                 sym.SetInputSection(isec);
             }
             obj.Symbols.insert(i, s.clone());
+            obj.LocalSymbols.push(s.clone());
         }
 
         let globals = n_locals..obj.ElfSyms.len();
@@ -186,21 +312,51 @@ impl Objectfile {
         }
     }
 
+    /// the rank of the definition of the `idx`th symbol. when several files define the
+    /// same symbol, the one with the lowest rank wins.
+    /// strong defined > common > weak > shared libraries > lazy(not extracted archive members)
+    pub fn GetRank(&self, idx: usize) -> u32 {
+        let esym = &self.ElfSyms[idx];
+        if !self.IsAlive {
+            return 5;
+        }
+        if self.IsDso() {
+            return 4;
+        }
+        if esym.IsCommon() {
+            return 2;
+        }
+        if esym.IsWeak() {
+            return 3;
+        }
+        1
+    }
+
     /// try to find out where the symbols come from, or the owner of each symbol
     pub fn ResolveSymbols(o: &Rc<RefCell<Self>>) {
-        let obj = o.borrow_mut();
+        let obj = o.borrow();
         // local symbols dont need to resolve, they just belong to that file
         for i in obj.FirstGlobal..obj.ElfSyms.len() {
             let esym = &obj.ElfSyms[i];
             let mut sym = obj.Symbols.get(&i).unwrap().borrow_mut();
+            // undefined references count as well. but the visibility
+            // in a shared library is none of our business
+            if obj.IsDso() {
+                if obj.IsHiddenVersion(i) {
+                    continue;
+                }
+            }
+            else {
+                sym.MergeVisibility(esym.Visibility());
+            }
 
             if esym.IsUndef() {
                 continue;
             }
 
             let mut isec = None;
-            // absolute symbols dont have related sections
-            if !esym.IsAbs() && !esym.IsCommon() {
+            // absolute symbols dont have related sections. nor do the ones in shared libraries
+            if !esym.IsAbs() && !esym.IsCommon() && !obj.IsDso() {
                 isec = obj.GetSection(esym, i);
                 if isec.is_none() {
                     continue;
@@ -209,7 +365,15 @@ impl Objectfile {
 
             // current esym is not undef, and file unknown. which means 
             // that the symbol is defined by current object file.
-            if sym.File.is_none() {
+            // otherwise take it over if we have a better definition
+            let better = match &sym.File {
+                None => true,
+                Some(owner) => {
+                    let owner = owner.borrow();
+                    obj.GetRank(i) < owner.GetRank(sym.SymIdx)
+                }
+            };
+            if better {
 //                debug!("{}: defined by {}", &sym.Name, obj.Name());
                 sym.File = Some(o.clone());
                 sym.SetInputSection(isec);
@@ -219,6 +383,30 @@ impl Objectfile {
         }
     }
 
+    /// find strong definitions of symbols that are owned by some other file
+    pub fn CollectDuplicateSymbols(o: &Rc<RefCell<Self>>, dups: &mut Vec<String>) {
+        let obj = o.borrow();
+        for i in obj.FirstGlobal..obj.ElfSyms.len() {
+            let esym = &obj.ElfSyms[i];
+            if esym.IsUndef() || esym.IsCommon() || obj.GetRank(i) != 1 {
+                continue;
+            }
+            if !esym.IsAbs() && obj.GetSection(esym, i).is_none() {
+                continue;
+            }
+
+            let sym = obj.Symbols.get(&i).unwrap().borrow();
+            let owner = match &sym.File {
+                Some(owner) if !Rc::ptr_eq(owner, o) => owner.borrow(),
+                _ => continue
+            };
+            if !owner.ElfSyms[sym.SymIdx].IsCommon() && owner.GetRank(sym.SymIdx) == 1 {
+                dups.push(format!("duplicate symbol: {}, defined in {} and {}",
+                    sym.Name, owner.DisplayName(), obj.DisplayName()));
+            }
+        }
+    }
+
     /// bug?
     pub fn ConvertCommonSymbols(o: &Rc<RefCell<Self>>, ctx: *mut Context) {
         let mut obj = o.borrow_mut();
@@ -242,7 +430,6 @@ impl Objectfile {
                     continue;
                 }
 
-                obj.ElfSections2.push(Default::default());
                 let mut shdr = Shdr{..Default::default()};
                 let name: String;
 
@@ -258,6 +445,7 @@ impl Objectfile {
                 shdr.Type = abi::SHT_NOBITS;
                 shdr.Size = obj.ElfSyms[i].Size as usize;
                 shdr.AddrAlign = obj.ElfSyms[i].Val;
+                obj.ElfSections2.push(shdr);
 
                 let idx = obj.ElfSections.len() + obj.ElfSections2.len() - 1;
                 drop(esym);
@@ -275,6 +463,10 @@ impl Objectfile {
 
     pub fn MarkLiveObjects(&mut self, roots: &mut Vec<Rc<RefCell<Objectfile>>>) {
         assert!(self.IsAlive);
+        // the undefined symbols of a shared library are resolved by the loader
+        if self.IsDso() {
+            return;
+        }
 
         for i in self.FirstGlobal..self.ElfSyms.len() {
             let sym = self.Symbols.get(&i).unwrap().borrow();
@@ -286,7 +478,8 @@ impl Objectfile {
                 continue;
             }
 
-            if esym.IsUndef() && !sym.FileAlive() {
+            // undefined weak references don't pull archive members in
+            if esym.IsUndef() && !esym.IsWeak() && !sym.FileAlive() {
                 if let Some(file) = &sym.File {
                     file.borrow_mut().IsAlive = true;
 //                    warn!("add alive '{}'", file.borrow().Name());
@@ -349,6 +542,29 @@ impl Objectfile {
 
         }
 
+        // relocations against a section symbol of a mergeable section use the
+        // addend to select the piece of data, so they must be redirected to
+        // the fragment that actually contains it
+        for isec in self.Sections.iter().flatten() {
+            let mut isec = isec.borrow_mut();
+            let mut relFragments = BTreeMap::new();
+            for (idx, rel) in isec.Rels.iter().enumerate() {
+                let esym = &self.ElfSyms[rel.Sym as usize];
+                if esym.Type() != STT_SECTION {
+                    continue;
+                }
+
+                if let Some(m) = &self.MergeableSections[self.GetShndx(esym, rel.Sym as usize)] {
+                    let offset = (esym.Val as i64 + rel.Addend) as u32;
+                    let (frag, fragOffset) = m.GetFragment(offset);
+                    match frag {
+                        Some(frag) => relFragments.insert(idx, (frag, fragOffset as u64)),
+                        None => { error!("bad relocation at {}", rel.Offset); }
+                    };
+                }
+            }
+            isec.RelFragments = relFragments;
+        }
     }
 
     pub fn IsAlive(&self) -> bool {
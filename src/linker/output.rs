@@ -2,7 +2,12 @@ use elf::abi::EF_RISCV_RVC;
 use crate::linker::elf::PAGESIZE;
 
 use super::common::*;
+use super::passes::isTbss;
 use super::inputsections::{InputSection, SectionFragment};
+use super::elf::{Sym, Dyn, Rela};
+use super::symbol::Symbol;
+use super::context::HashStyle;
+use std::mem::size_of;
 
 /// an abstract base writting unit
 #[derive(Default,Debug, Clone)]
@@ -30,6 +35,139 @@ pub struct OutputPhdr {
 	pub Phdrs:	Vec<Phdr>,
 }
 
+/// section name string table
+#[derive(Default, Clone)]
+pub struct OutputShstrtab {
+	pub Chunk:		Chunk,
+	pub Contents:	Vec<u8>,
+}
+
+/// the output symbol table. local symbols come first
+#[derive(Default)]
+pub struct OutputSymtab {
+	pub Chunk:			Chunk,
+	pub Symbols:		Vec<Rc<RefCell<Symbol>>>,
+	/// where each symbol's name is in .strtab
+	pub NameOffsets:	Vec<u32>,
+}
+
+/// symbol name string table. filled up by `OutputSymtab`
+#[derive(Default, Clone)]
+pub struct OutputStrtab {
+	pub Chunk:		Chunk,
+	pub Contents:	Vec<u8>,
+}
+
+/// `.dynamic`. what the dynamic linker, or a static PIE itself, needs to know
+#[derive(Default, Clone)]
+pub struct OutputDynamic {
+	pub Chunk:		Chunk,
+	/// .dynstr offsets of the sonames of the shared libraries needed
+	pub Needed:		Vec<u32>,
+}
+
+/// `.rela.dyn`. relocations applied at startup, by the dynamic linker or by the program itself
+#[derive(Default)]
+pub struct OutputRelaDyn {
+	pub Chunk:		Chunk,
+	/// (section, index into its `Rels`) of the R_RISCV_64 relocations left to the runtime.
+	/// they become R_RISCV_RELATIVE, unless the symbol is imported
+	pub Relocs:		Vec<(Rc<RefCell<InputSection>>, usize)>,
+}
+
+/// `.interp`. the path of the dynamic linker
+#[derive(Default, Clone)]
+pub struct OutputInterp {
+	pub Chunk:		Chunk,
+}
+
+/// `.dynsym`. imported symbols come first, and the defined ones are sorted for .gnu.hash
+#[derive(Default)]
+pub struct OutputDynsym {
+	pub Chunk:			Chunk,
+	pub Symbols:		Vec<Rc<RefCell<Symbol>>>,
+	/// where each symbol's name is in .dynstr
+	pub NameOffsets:	Vec<u32>,
+}
+
+/// `.dynstr`. filled up along with .dynsym
+#[derive(Default, Clone)]
+pub struct OutputDynstr {
+	pub Chunk:		Chunk,
+	pub Contents:	Vec<u8>,
+}
+
+/// `.hash`. the SysV hash table of .dynsym
+#[derive(Default, Clone)]
+pub struct OutputHash {
+	pub Chunk:		Chunk,
+}
+
+/// `.gnu.hash`. only covers the defined symbols of .dynsym, from `SymOffset`
+#[derive(Default, Clone)]
+pub struct OutputGnuHash {
+	pub Chunk:		Chunk,
+	pub NumBuckets:	usize,
+	pub SymOffset:	usize,
+	/// the number of 64-bit words of the bloom filter
+	pub BloomSize:	usize,
+}
+
+/// `.got`. addresses of the symbols referred to through R_RISCV_GOT_HI20
+#[derive(Default)]
+pub struct OutputGot {
+	pub Chunk:		Chunk,
+	pub Symbols:	Vec<Rc<RefCell<Symbol>>>,
+}
+
+/// `.got.plt`. two words for the dynamic linker, then one slot for each PLT entry
+#[derive(Default, Clone)]
+pub struct OutputGotPlt {
+	pub Chunk:		Chunk,
+}
+
+/// `.plt`. a header to call the lazy resolver, and one entry for each imported function
+#[derive(Default)]
+pub struct OutputPlt {
+	pub Chunk:		Chunk,
+	pub Symbols:	Vec<Rc<RefCell<Symbol>>>,
+}
+
+/// `.rela.plt`. the R_RISCV_JUMP_SLOT for each .got.plt slot
+#[derive(Default, Clone)]
+pub struct OutputRelaPlt {
+	pub Chunk:		Chunk,
+}
+
+/// `.iplt`. one entry for each IFUNC symbol, jumping to the address in its GOT slot
+#[derive(Default)]
+pub struct OutputIplt {
+	pub Chunk:		Chunk,
+	pub Symbols:	Vec<Rc<RefCell<Symbol>>>,
+}
+
+/// `.rela.iplt`. the R_RISCV_IRELATIVE of static executables, which
+/// libc applies by itself, from `__rela_iplt_start` to `__rela_iplt_end`
+#[derive(Default, Clone)]
+pub struct OutputRelaIplt {
+	pub Chunk:		Chunk,
+}
+
+/// `.eh_frame_hdr`. a table of the FDEs sorted by function addresses,
+/// for the unwinder to find them by binary search. see `PT_GNU_EH_FRAME`
+#[derive(Default, Clone)]
+pub struct OutputEhFrameHdr {
+	pub Chunk:		Chunk,
+}
+
+/// `.dynbss`. space for the data copied from shared libraries by R_RISCV_COPY
+#[derive(Default)]
+pub struct OutputDynbss {
+	pub Chunk:		Chunk,
+	/// (symbol, offset). aliases share the offset of the symbol right before them
+	pub Symbols:	Vec<(Rc<RefCell<Symbol>>, usize)>,
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct OutputSection {
 	pub Chunk:		Chunk,
@@ -38,136 +176,822 @@ pub struct OutputSection {
 	pub Idx:		usize,
 }
 
-#[derive(Default,Debug)]
-pub struct MergedSection {
-	pub Chunk:	Chunk,
-	/// note: key is not always strings
-	pub Map:	BTreeMap<String, Rc<RefCell<SectionFragment>>>,
-}
+#[derive(Default,Debug)]
+pub struct MergedSection {
+	pub Chunk:	Chunk,
+	/// note: key is not always strings
+	pub Map:	BTreeMap<String, Rc<RefCell<SectionFragment>>>,
+}
+
+impl Deref for MergedSection {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for MergedSection {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputSection {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputSection {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputShdr{
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputShdr {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputPhdr {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputPhdr {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputShstrtab {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputShstrtab {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputSymtab {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputSymtab {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputStrtab {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputStrtab {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputDynamic {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputDynamic {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputRelaDyn {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputRelaDyn {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputInterp {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputInterp {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputDynsym {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputDynsym {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputDynstr {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputDynstr {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputHash {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputHash {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputGnuHash {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputGnuHash {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputGot {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputGot {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputGotPlt {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputGotPlt {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputPlt {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputPlt {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputIplt {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputIplt {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputRelaIplt {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputRelaIplt {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputEhFrameHdr {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputEhFrameHdr {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputRelaPlt {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputRelaPlt {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputDynbss {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl DerefMut for OutputDynbss {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.Chunk
+	}
+}
+
+impl Deref for OutputEhdr {
+	type Target = Chunk;
+	fn deref(&self) -> &Self::Target {
+		&self.Chunk
+	}
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Chunk {
+            Shdr: Shdr{
+				AddrAlign: 1,
+				..Default::default()
+			},
+            ..Default::default()
+        }
+    }
+}
+
+impl OutputEhdr {
+	pub fn new() -> Box<Self> {
+		let mut Chunk = Chunk::new();
+		let shdr = Shdr{
+			Flags:		abi::SHF_ALLOC as u64,
+			Size:		EHDR_SIZE,
+			AddrAlign:	8,
+			..Default::default()
+		};
+
+		Chunk.Shdr = shdr;
+		Box::new(OutputEhdr { Chunk })
+	}
+}
+
+impl OutputSection {
+	pub fn new(name: String, ty: u32, flags: u64, idx: usize) -> Box<Self> {
+		let mut o = Self {
+			Chunk: Chunk::new(),
+			..Default::default()
+		};
+		o.Name = name;
+		o.Shdr.Type = ty;
+		o.Shdr.Flags = flags;
+		o.Idx = idx;
+
+		Box::new(o)
+	}
+}
+
+
+impl OutputShdr {
+	pub fn new() -> Box<Self> {
+		let mut o = OutputShdr{Chunk: Chunk::new()};
+		o.Shdr.AddrAlign = 8;
+		Box::new(o)
+	}
+}
+
+impl OutputPhdr {
+	pub fn new() -> Box<Self> {
+		let mut o = Self {
+			Chunk: Chunk::new(),
+			..Default::default()
+		};
+		o.Shdr.Flags = abi::SHF_ALLOC as u64;
+		o.Shdr.AddrAlign = 8;
+
+		Box::new(o)
+	}
+}
+
+impl OutputShstrtab {
+	pub fn new() -> Box<Self> {
+		let mut o = Self {
+			Chunk: Chunk::new(),
+			..Default::default()
+		};
+		o.Name = ".shstrtab".into();
+		o.Shdr.Type = abi::SHT_STRTAB;
+
+		Box::new(o)
+	}
+}
+
+impl OutputSymtab {
+	pub fn new() -> Box<Self> {
+		let mut o = Self {
+			Chunk: Chunk::new(),
+			..Default::default()
+		};
+		o.Name = ".symtab".into();
+		o.Shdr.Type = abi::SHT_SYMTAB;
+		o.Shdr.EntSize = size_of::<Sym>();
+		o.Shdr.AddrAlign = 8;
+
+		Box::new(o)
+	}
+
+	/// section symbols and the assembler's temporary labels are useless in
+	/// an executable, and symbols in discarded sections have nowhere to point to
+	pub fn ShouldWrite(sym: &Symbol, esym: &Sym) -> bool {
+		if esym.Type() == abi::STT_SECTION || sym.Name.starts_with(".L") {
+			return false;
+		}
+		if sym.SectionFragment.is_some() || esym.IsAbs() {
+			return true;
+		}
+		match &sym.InputSection {
+			Some(isec) => isec.borrow().IsAlive,
+			None => false,
+		}
+	}
+
+	/// the index of the output section where the symbol lives
+	pub fn GetOutputShndx(sym: &Symbol) -> u16 {
+		if let Some(frag) = &sym.SectionFragment {
+			return frag.borrow().OutputSection.borrow().Shndx as u16;
+		}
+		if let Some(isec) = &sym.InputSection {
+			return isec.borrow().OutputSection.borrow().Shndx as u16;
+		}
+		abi::SHN_ABS
+	}
+}
+
+impl OutputStrtab {
+	pub fn new() -> Box<Self> {
+		let mut o = Self {
+			Chunk: Chunk::new(),
+			..Default::default()
+		};
+		o.Name = ".strtab".into();
+		o.Shdr.Type = abi::SHT_STRTAB;
+
+		Box::new(o)
+	}
+}
+
+impl OutputDynamic {
+	pub fn new() -> Box<Self> {
+		let mut o = Self {
+			Chunk: Chunk::new(),
+			..Default::default()
+		};
+		o.Name = ".dynamic".into();
+		o.Shdr.Type = abi::SHT_DYNAMIC;
+		o.Shdr.Flags = (abi::SHF_ALLOC | abi::SHF_WRITE) as u64;
+		o.Shdr.EntSize = size_of::<Dyn>();
+		o.Shdr.AddrAlign = 8;
+
+		Box::new(o)
+	}
+
+	pub fn GetEntries(ctx: &Context) -> Vec<Dyn> {
+		let mut vec = vec![];
+		let mut define = |Tag: i64, Val: u64| vec.push(Dyn { Tag, Val });
 
-impl Deref for MergedSection {
-	type Target = Chunk;
-	fn deref(&self) -> &Self::Target {
-		&self.Chunk
+		for offset in &ctx.Dynamic.Needed {
+			define(abi::DT_NEEDED, *offset as u64);
+		}
+
+		let dynamic = ctx.IsDynamic();
+		if dynamic {
+			if ctx.Args.HashStyle != HashStyle::Gnu {
+				define(abi::DT_HASH, ctx.Hash.Shdr.Addr);
+			}
+			if ctx.Args.HashStyle != HashStyle::Sysv {
+				define(abi::DT_GNU_HASH, ctx.GnuHash.Shdr.Addr);
+			}
+		}
+		define(abi::DT_STRTAB, ctx.Dynstr.Shdr.Addr);
+		define(abi::DT_SYMTAB, ctx.Dynsym.Shdr.Addr);
+		define(abi::DT_STRSZ, ctx.Dynstr.Contents.len() as u64);
+		define(abi::DT_SYMENT, size_of::<Sym>() as u64);
+
+		let rela = &ctx.RelaDyn;
+		let relocs = OutputRelaDyn::GetEntries(ctx);
+		if !relocs.is_empty() {
+			define(abi::DT_RELA, rela.Shdr.Addr);
+			define(abi::DT_RELASZ, (relocs.len() * size_of::<Rela>()) as u64);
+			define(abi::DT_RELAENT, size_of::<Rela>() as u64);
+			define(abi::DT_RELACOUNT, relocs.iter().filter(|r| r.Type == abi::R_RISCV_RELATIVE).count() as u64);
+		}
+
+		if !ctx.Plt.Symbols.is_empty() {
+			define(abi::DT_PLTGOT, ctx.GotPlt.Shdr.Addr);
+			define(abi::DT_PLTRELSZ, (ctx.Plt.Symbols.len() * size_of::<Rela>()) as u64);
+			define(abi::DT_PLTREL, abi::DT_RELA as u64);
+			define(abi::DT_JMPREL, ctx.RelaPlt.Shdr.Addr);
+		}
+
+		// filled by the dynamic linker, for debuggers
+		if dynamic {
+			define(abi::DT_DEBUG, 0);
+		}
+		if ctx.Args.Pie {
+			define(abi::DT_FLAGS_1, abi::DF_1_PIE as u64);
+		}
+		define(abi::DT_NULL, 0);
+		vec
 	}
 }
 
-impl DerefMut for MergedSection {
-	fn deref_mut(&mut self) -> &mut Self::Target {
-		&mut self.Chunk
+impl OutputRelaDyn {
+	pub fn new() -> Box<Self> {
+		let mut o = Self {
+			Chunk: Chunk::new(),
+			..Default::default()
+		};
+		o.Name = ".rela.dyn".into();
+		o.Shdr.Type = abi::SHT_RELA;
+		o.Shdr.Flags = abi::SHF_ALLOC as u64;
+		o.Shdr.EntSize = size_of::<Rela>();
+		o.Shdr.AddrAlign = 8;
+
+		Box::new(o)
 	}
-}
 
-impl Deref for OutputSection {
-	type Target = Chunk;
-	fn deref(&self) -> &Self::Target {
-		&self.Chunk
+	/// R_RISCV_RELATIVE ones come first, as counted by DT_RELACOUNT.
+	/// R_RISCV_IRELATIVE ones come last, as resolvers may use the others
+	pub fn GetEntries(ctx: &Context) -> Vec<Rela> {
+		let mut relative = vec![];
+		let mut symbolic = vec![];
+
+		for (isec, idx) in &ctx.RelaDyn.Relocs {
+			let isec = isec.borrow();
+			let rel = &isec.Rels[*idx];
+			let file = isec.File.borrow();
+			let sym = file.Symbols.get(&(rel.Sym as usize)).unwrap().borrow();
+			let offset = isec.GetAddr() + rel.Offset - isec.GetDelta(rel.Offset);
+
+			if sym.IsImported() {
+				symbolic.push(Rela { Offset: offset, Type: abi::R_RISCV_64, Sym: sym.DynsymIdx.unwrap() as u32, Addend: rel.Addend });
+			}
+			else {
+				// the pointer is rebased by the load address at startup
+				relative.push(Rela { Offset: offset, Type: abi::R_RISCV_RELATIVE, Sym: 0, Addend: isec.GetSA(*idx, rel, &sym) as i64 });
+			}
+		}
+
+		for sym in &ctx.Got.Symbols {
+			let sym = sym.borrow();
+			let offset = sym.GetGotAddr(ctx);
+			if sym.IpltIdx.is_some() {
+				continue;
+			}
+			if sym.IsTls() {
+				// the offset from tp is only known at runtime for imported ones
+				if sym.IsImported() {
+					symbolic.push(Rela { Offset: offset, Type: abi::R_RISCV_TLS_TPREL64, Sym: sym.DynsymIdx.unwrap() as u32, Addend: 0 });
+				}
+			}
+			else if sym.IsImported() {
+				symbolic.push(Rela { Offset: offset, Type: abi::R_RISCV_64, Sym: sym.DynsymIdx.unwrap() as u32, Addend: 0 });
+			}
+			else if ctx.Args.Pie && !sym.IsAbsolute(ctx) {
+				relative.push(Rela { Offset: offset, Type: abi::R_RISCV_RELATIVE, Sym: 0, Addend: sym.GetAddr() as i64 });
+			}
+		}
+
+		let mut prev = None;
+		for (sym, offset) in &ctx.Dynbss.Symbols {
+			if prev == Some(*offset) {
+				continue;
+			}
+			prev = Some(*offset);
+			let sym = sym.borrow();
+			symbolic.push(Rela { Offset: sym.GetAddr(), Type: abi::R_RISCV_COPY, Sym: sym.DynsymIdx.unwrap() as u32, Addend: 0 });
+		}
+
+		relative.extend(symbolic);
+		relative.extend(OutputIplt::GetEntries(ctx));
+		relative
 	}
 }
 
-impl DerefMut for OutputSection {
-	fn deref_mut(&mut self) -> &mut Self::Target {
-		&mut self.Chunk
+impl OutputInterp {
+	pub fn new() -> Box<Self> {
+		let mut o = Self {
+			Chunk: Chunk::new(),
+		};
+		o.Name = ".interp".into();
+		o.Shdr.Type = abi::SHT_PROGBITS;
+		o.Shdr.Flags = abi::SHF_ALLOC as u64;
+		o.Shdr.AddrAlign = 1;
+
+		Box::new(o)
 	}
 }
 
-impl Deref for OutputShdr{
-	type Target = Chunk;
-	fn deref(&self) -> &Self::Target {
-		&self.Chunk
+impl OutputDynsym {
+	pub fn new() -> Box<Self> {
+		let mut o = Self {
+			Chunk: Chunk::new(),
+			..Default::default()
+		};
+		o.Name = ".dynsym".into();
+		o.Shdr.Type = abi::SHT_DYNSYM;
+		o.Shdr.Flags = abi::SHF_ALLOC as u64;
+		o.Shdr.EntSize = size_of::<Sym>();
+		o.Shdr.AddrAlign = 8;
+		// only the null symbol is local
+		o.Shdr.Info = 1;
+
+		Box::new(o)
 	}
 }
 
-impl DerefMut for OutputShdr {
-	fn deref_mut(&mut self) -> &mut Self::Target {
-		&mut self.Chunk
+impl OutputDynstr {
+	pub fn new() -> Box<Self> {
+		let mut o = Self {
+			Chunk: Chunk::new(),
+			Contents: vec![0],
+		};
+		o.Name = ".dynstr".into();
+		o.Shdr.Type = abi::SHT_STRTAB;
+		o.Shdr.Flags = abi::SHF_ALLOC as u64;
+		o.Shdr.AddrAlign = 1;
+
+		Box::new(o)
+	}
+
+	/// returns the offset of the string
+	pub fn Add(&mut self, s: &str) -> u32 {
+		let offset = self.Contents.len() as u32;
+		self.Contents.extend_from_slice(s.as_bytes());
+		self.Contents.push(0);
+		offset
 	}
 }
 
-impl Deref for OutputPhdr {
-	type Target = Chunk;
-	fn deref(&self) -> &Self::Target {
-		&self.Chunk
+impl OutputHash {
+	pub fn new() -> Box<Self> {
+		let mut o = Self {
+			Chunk: Chunk::new(),
+		};
+		o.Name = ".hash".into();
+		o.Shdr.Type = abi::SHT_HASH;
+		o.Shdr.Flags = abi::SHF_ALLOC as u64;
+		o.Shdr.EntSize = 4;
+		o.Shdr.AddrAlign = 8;
+
+		Box::new(o)
 	}
 }
 
-impl DerefMut for OutputPhdr {
-	fn deref_mut(&mut self) -> &mut Self::Target {
-		&mut self.Chunk
+impl OutputGnuHash {
+	/// the bloom filter uses two bits of the hash, the low 6 bits and the ones shifted by this
+	pub const BLOOM_SHIFT: u32 = 26;
+
+	pub fn new() -> Box<Self> {
+		let mut o = Self {
+			Chunk: Chunk::new(),
+			..Default::default()
+		};
+		o.Name = ".gnu.hash".into();
+		o.Shdr.Type = abi::SHT_GNU_HASH;
+		o.Shdr.Flags = abi::SHF_ALLOC as u64;
+		o.Shdr.AddrAlign = 8;
+
+		Box::new(o)
 	}
 }
 
-impl Deref for OutputEhdr {
-	type Target = Chunk;
-	fn deref(&self) -> &Self::Target {
-		&self.Chunk
+impl OutputGot {
+	pub fn new() -> Box<Self> {
+		let mut o = Self {
+			Chunk: Chunk::new(),
+			..Default::default()
+		};
+		o.Name = ".got".into();
+		o.Shdr.Type = abi::SHT_PROGBITS;
+		o.Shdr.Flags = (abi::SHF_ALLOC | abi::SHF_WRITE) as u64;
+		o.Shdr.EntSize = 8;
+		o.Shdr.AddrAlign = 8;
+
+		Box::new(o)
 	}
 }
 
-impl Chunk {
-    pub fn new() -> Self {
-        Chunk {
-            Shdr: Shdr{
-				AddrAlign: 1,
-				..Default::default()
-			},
-            ..Default::default()
-        }
-    }
+impl OutputGotPlt {
+	/// reserved for the dynamic linker. the resolver and the link map
+	pub const HEADER_SIZE: usize = 16;
+
+	pub fn new() -> Box<Self> {
+		let mut o = Self {
+			Chunk: Chunk::new(),
+		};
+		o.Name = ".got.plt".into();
+		o.Shdr.Type = abi::SHT_PROGBITS;
+		o.Shdr.Flags = (abi::SHF_ALLOC | abi::SHF_WRITE) as u64;
+		o.Shdr.EntSize = 8;
+		o.Shdr.AddrAlign = 8;
+
+		Box::new(o)
+	}
 }
 
-impl OutputEhdr {
+impl OutputPlt {
+	pub const HEADER_SIZE: usize = 32;
+	pub const ENTRY_SIZE: usize = 16;
+
 	pub fn new() -> Box<Self> {
-		let mut Chunk = Chunk::new();
-		let shdr = Shdr{
-			Flags:		abi::SHF_ALLOC as u64,
-			Size:		EHDR_SIZE,
-			AddrAlign:	8,
+		let mut o = Self {
+			Chunk: Chunk::new(),
 			..Default::default()
 		};
+		o.Name = ".plt".into();
+		o.Shdr.Type = abi::SHT_PROGBITS;
+		o.Shdr.Flags = (abi::SHF_ALLOC | abi::SHF_EXECINSTR) as u64;
+		o.Shdr.AddrAlign = 16;
 
-		Chunk.Shdr = shdr;
-		Box::new(OutputEhdr { Chunk })
+		Box::new(o)
 	}
 }
 
-impl OutputSection {
-	pub fn new(name: String, ty: u32, flags: u64, idx: usize) -> Box<Self> {
+impl OutputIplt {
+	pub const ENTRY_SIZE: usize = 16;
+
+	pub fn new() -> Box<Self> {
 		let mut o = Self {
 			Chunk: Chunk::new(),
 			..Default::default()
 		};
-		o.Name = name;
-		o.Shdr.Type = ty;
-		o.Shdr.Flags = flags;
-		o.Idx = idx;
+		o.Name = ".iplt".into();
+		o.Shdr.Type = abi::SHT_PROGBITS;
+		o.Shdr.Flags = (abi::SHF_ALLOC | abi::SHF_EXECINSTR) as u64;
+		o.Shdr.AddrAlign = 16;
 
 		Box::new(o)
 	}
-}
 
+	/// the R_RISCV_IRELATIVE filling the GOT slots by calling the resolvers
+	pub fn GetEntries(ctx: &Context) -> Vec<Rela> {
+		ctx.Iplt.Symbols.iter().map(|sym| {
+			let sym = sym.borrow();
+			Rela { Offset: sym.GetGotAddr(ctx), Type: abi::R_RISCV_IRELATIVE, Sym: 0, Addend: sym.GetDefinedAddr() as i64 }
+		}).collect()
+	}
+}
 
-impl OutputShdr {
+impl OutputRelaIplt {
 	pub fn new() -> Box<Self> {
-		let mut o = OutputShdr{Chunk: Chunk::new()};
+		let mut o = Self {
+			Chunk: Chunk::new(),
+		};
+		o.Name = ".rela.iplt".into();
+		o.Shdr.Type = abi::SHT_RELA;
+		o.Shdr.Flags = abi::SHF_ALLOC as u64;
+		o.Shdr.EntSize = size_of::<Rela>();
 		o.Shdr.AddrAlign = 8;
+
 		Box::new(o)
 	}
 }
 
-impl OutputPhdr {
+impl OutputEhFrameHdr {
+	/// version, and the encodings of eh_frame_ptr, fde_count and the table
+	pub const HEADER_SIZE: usize = 12;
+	pub const ENTRY_SIZE: usize = 8;
+
 	pub fn new() -> Box<Self> {
 		let mut o = Self {
 			Chunk: Chunk::new(),
-			..Default::default()
 		};
+		o.Name = ".eh_frame_hdr".into();
+		o.Shdr.Type = abi::SHT_PROGBITS;
 		o.Shdr.Flags = abi::SHF_ALLOC as u64;
+		o.Shdr.AddrAlign = 4;
+
+		Box::new(o)
+	}
+}
+
+impl OutputRelaPlt {
+	pub fn new() -> Box<Self> {
+		let mut o = Self {
+			Chunk: Chunk::new(),
+		};
+		o.Name = ".rela.plt".into();
+		o.Shdr.Type = abi::SHT_RELA;
+		o.Shdr.Flags = (abi::SHF_ALLOC | abi::SHF_INFO_LINK) as u64;
+		o.Shdr.EntSize = size_of::<Rela>();
 		o.Shdr.AddrAlign = 8;
 
 		Box::new(o)
 	}
 }
 
+impl OutputDynbss {
+	pub fn new() -> Box<Self> {
+		let mut o = Self {
+			Chunk: Chunk::new(),
+			..Default::default()
+		};
+		o.Name = ".dynbss".into();
+		o.Shdr.Type = abi::SHT_NOBITS;
+		o.Shdr.Flags = (abi::SHF_ALLOC | abi::SHF_WRITE) as u64;
+		o.Shdr.AddrAlign = 1;
+
+		Box::new(o)
+	}
+}
+
+/// the hash function of .hash
+pub fn ElfHash(name: &str) -> u32 {
+	let mut h: u32 = 0;
+	for c in name.bytes() {
+		h = (h << 4).wrapping_add(c as u32);
+		let g = h & 0xf000_0000;
+		if g != 0 {
+			h ^= g >> 24;
+		}
+		h &= !g;
+	}
+	h
+}
+
+/// the hash function of .gnu.hash
+pub fn GnuHash(name: &str) -> u32 {
+	name.bytes().fold(5381u32, |h, c| h.wrapping_mul(33).wrapping_add(c as u32))
+}
+
 impl MergedSection {
 	pub fn new(name: &str, flags: u64, ty: u32) -> Rc<RefCell<MergedSection>> {
 		let mut m = MergedSection {
@@ -182,8 +1006,8 @@ impl MergedSection {
 		m.ToRcRefcell()
 	}
 
+	/// `name` is the name of the output section
 	pub fn GetInstance(ctx: &mut Context, name: &str, ty: u32, flags: u64) -> Rc<RefCell<Self>> {
-		let name = GetOutputName(name, flags);
 		// ignore these flags
 		let flags = flags &
 			!abi::SHF_GROUP as u64 & !abi::SHF_MERGE as u64 &
@@ -199,7 +1023,7 @@ impl MergedSection {
 		match osec {
 			Some(o) => o.clone(),
 			None => {
-				let osec = MergedSection::new(&name, flags, ty);
+				let osec = MergedSection::new(name, flags, ty);
 				ctx.MergedSections.push(osec.clone());
 				osec
 			}
@@ -283,6 +1107,13 @@ pub fn GetOutputName(name: &str, flags: u64) -> String {
 		};
 	}
 
+	// common symbols are given their space here
+	match name {
+		".common" => return ".bss".into(),
+		".tls_common" => return ".tbss".into(),
+		_ => {}
+	}
+
 	for prefix in PREFIXES {
 		let stem = &prefix[..prefix.len() - 1];	// remove the last '.'
 		if name == stem || name.starts_with(prefix) {
@@ -293,6 +1124,16 @@ pub fn GetOutputName(name: &str, flags: u64) -> String {
 	name.into()
 }
 
+/// sections like `my_registry` get `__start_my_registry` and `__stop_my_registry`
+pub fn IsCIdentifier(name: &str) -> bool {
+	let mut chars = name.chars();
+	match chars.next() {
+		Some(c) if c.is_ascii_alphabetic() || c == '_' =>
+			chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+		_ => false
+	}
+}
+
 /// return existed os, or create a new one
 pub fn GetOutputSection(ctx: &mut Context, name: String, ty: u32, flags: u64) -> Rc<RefCell<OutputSection>> {
 	let name = GetOutputName(&name, flags);
@@ -317,13 +1158,55 @@ pub fn GetOutputSection(ctx: &mut Context, name: String, ty: u32, flags: u64) ->
 	}
 }
 
+/// the output section a linker script told us to use. sections with
+/// different flags and types go to the same one, as long as the name matches
+pub fn GetScriptOutputSection(ctx: &mut Context, name: String, ty: u32, flags: u64, noload: bool) -> Rc<RefCell<OutputSection>> {
+	let flags = flags & !abi::SHF_GROUP as u64 & !abi::SHF_COMPRESSED as u64 & !abi::SHF_LINK_ORDER as u64
+		& !abi::SHF_MERGE as u64 & !abi::SHF_STRINGS as u64;
+	let ty = match noload {
+		true => abi::SHT_NOBITS,
+		false => ty
+	};
+
+	if let Some(osec) = ctx.OutputSections.iter().find(|osec| osec.borrow().Name == name) {
+		let mut o = osec.borrow_mut();
+		o.Shdr.Flags |= flags;
+		// contents win over zeros
+		if o.Shdr.Type == abi::SHT_NOBITS && ty != abi::SHT_NOBITS {
+			o.Shdr.Type = ty;
+		}
+		return osec.clone();
+	}
+
+	let idx = ctx.OutputSections.len();
+	let osec = (*OutputSection::new(name, ty, flags, idx)).ToRcRefcell();
+	ctx.OutputSections.push(osec.clone());
+	osec
+}
+
+/// `-e` could be either a symbol or an address. like GNU ld, try symbol first
 pub fn GetEntryAddr(ctx: *mut Box<Context>) -> u64 {
 	let ctx = ptr2ref(ctx);
+	let entry = &ctx.Args.Entry;
+	if let Some(sym) = ctx.SymbolMap.get(entry) {
+		let sym = sym.borrow();
+		if sym.File.is_some() {
+			return sym.GetAddr();
+		}
+	}
+
+	if let Some(addr) = ParseNumber(entry) {
+		return addr;
+	}
+
 	for osec in &ctx.OutputSections {
 		if osec.borrow().Name == ".text" {
-			return osec.borrow().Shdr.Addr;
+			let addr = osec.borrow().Shdr.Addr;
+			warn!("cannot find entry symbol {}; defaulting to {:#x}", entry, addr);
+			return addr;
 		}
 	}
+	warn!("cannot find entry symbol {}; not setting start address", entry);
 	0
 }
 
@@ -349,6 +1232,39 @@ pub fn ptr2ref_dyn(ptr: *mut dyn Chunker) -> &'static mut dyn Chunker {
 	unsafe {&mut *ptr}
 }
 
+/// where the chunk is loaded from. differs from the address only when a
+/// linker script says so, e.g. `.data : { ... } > RAM AT > FLASH`
+pub fn GetLoadAddr(ctx: &Context, chunk: &mut dyn Chunker) -> u64 {
+	let addr = chunk.GetShdr().Addr;
+	match &ctx.Script {
+		Some(script) => addr.wrapping_add(*script.LoadOffsets.get(chunk.GetName()).unwrap_or(&0)),
+		None => addr
+	}
+}
+
+/// whether `c` can't be put into the same PT_LOAD as `prev`, the alloc chunk before it
+pub fn IsNewSegment(ctx: &Context, prev: *mut dyn Chunker, c: *mut dyn Chunker) -> bool {
+	let (prev, c) = (ptr2ref_dyn(prev), ptr2ref_dyn(c));
+	if prev.toPhdrFlags() != c.toPhdrFlags() || (prev.isBss() && !c.isBss()) {
+		return true;
+	}
+
+	// the file image of a segment must be contiguous, so are the addresses
+	let end = prev.GetShdr().Addr + prev.GetShdr().Size as u64;
+	let addr = c.GetShdr().Addr;
+	if addr < end || addr - end >= PAGESIZE {
+		return true;
+	}
+	if GetLoadAddr(ctx, prev).wrapping_sub(prev.GetShdr().Addr) != GetLoadAddr(ctx, c).wrapping_sub(addr) {
+		return true;
+	}
+
+	match &ctx.Script {
+		Some(script) => script.Segments.get(prev.GetName()) != script.Segments.get(c.GetName()),
+		None => false
+	}
+}
+
 pub fn createPhdr(ctx: &mut Context) -> Vec<Phdr> {
 	let vec = RefCell::new(vec![]);
 
@@ -384,8 +1300,26 @@ pub fn createPhdr(ctx: &mut Context) -> Vec<Phdr> {
 		phdr.MemSize = shdr.Addr + shdr.Size as u64 - phdr.VAddr;
 	};
 
-	// the 1st phdr should point to the phdr table itself
-	define(abi::PT_PHDR, abi::PF_R, 8, &mut *ctx.Phdr);
+	if let Some(script) = ctx.Script.take() {
+		let phdrs = match script.Phdrs.is_empty() {
+			true => None,
+			false => Some(script.CreatePhdrs(ctx)),
+		};
+		ctx.Script = Some(script);
+		if let Some(phdrs) = phdrs {
+			return phdrs;
+		}
+	}
+
+	// the 1st phdr should point to the phdr table itself.
+	// it isn't loaded when a linker script is used
+	if ctx.Phdr.Shdr.Flags & abi::SHF_ALLOC as u64 != 0 {
+		define(abi::PT_PHDR, abi::PF_R, 8, &mut *ctx.Phdr);
+	}
+
+	if ctx.NeedsInterp() {
+		define(abi::PT_INTERP, abi::PF_R, 1, &mut *ctx.Interp);
+	}
 
 	let end = ctx.Chunks.len();
 	let mut i = 0;
@@ -401,7 +1335,7 @@ pub fn createPhdr(ctx: &mut Context) -> Vec<Phdr> {
 		define(abi::PT_NOTE, flags, alignment, first);
 		while i < end {
 			let chunk = ptr2ref_dyn(ctx.Chunks[i]);
-			if !chunk.isNote() || !chunk.toPhdrFlags() == flags {
+			if !chunk.isNote() || chunk.toPhdrFlags() != flags {
 				break;
 			}
 
@@ -410,46 +1344,33 @@ pub fn createPhdr(ctx: &mut Context) -> Vec<Phdr> {
 		}
 	}
 
-	// bss
-	{
-		let mut chunks = ctx.Chunks.clone();
-		chunks.retain(|c| {
-			!ptr2ref_dyn(*c).isBss()
-		});
-
-		let end = chunks.len();
-		let mut i = 0;
-		while i < end {
-			let first = ptr2ref_dyn(chunks[i]);
-			i += 1;
-
-			if first.GetShdr().Flags & abi::SHF_ALLOC as u64 == 0 {
-				break;
-			}
+	// .tbss doesn't occupy any space in the image. it only exists in PT_TLS
+	let chunks: Vec<*mut dyn Chunker> = ctx.Chunks.iter()
+		.filter(|c| {
+			let c = ptr2ref_dyn(**c);
+			c.GetShdr().Flags & abi::SHF_ALLOC as u64 != 0 && !isTbss(c)
+		})
+		.copied().collect();
+	for i in 0..chunks.len() {
+		let c = ptr2ref_dyn(chunks[i]);
+		if i == 0 || IsNewSegment(ctx, chunks[i - 1], chunks[i]) {
+			let flags = c.toPhdrFlags();
+			define(abi::PT_LOAD, flags, PAGESIZE, c);
+			let len = vec.borrow().len();
+			vec.borrow_mut()[len - 1].PAddr = GetLoadAddr(ctx, c);
+		}
+		else {
+			push(c);
+		}
+	}
 
-			let flags = first.toPhdrFlags();
-			define(abi::PT_LOAD, flags, PAGESIZE, first);
-
-			if !first.isBss() {
-				while i < end {
-					let c = ptr2ref_dyn(chunks[i]);
-					if !(c.toPhdrFlags() == flags) || c.isBss() {
-						break;
-					}
-					push(c);
-					i += 1;
-				}
-			}
+	if ctx.NeedsDynamic() {
+		define(abi::PT_DYNAMIC, abi::PF_R | abi::PF_W, 8, &mut *ctx.Dynamic);
+	}
 
-			while i < end {
-				let c = ptr2ref_dyn(chunks[i]);
-				if !c.isBss() || !(c.toPhdrFlags() == flags) {
-					break;
-				}
-				push(c);
-				i += 1;
-			}
-		}
+	// only registered as a chunk when there's something in .eh_frame
+	if ctx.EhFrameHdr.Shndx != 0 {
+		define(abi::PT_GNU_EH_FRAME, abi::PF_R, 4, &mut *ctx.EhFrameHdr);
 	}
 
 	let mut i = 0;
@@ -1,7 +1,13 @@
 use super::common::*;
-use super::elf::IMAGE_BASE;
-use super::output::{OutputEhdr, OutputShdr, ptr2ref_dyn, OutputPhdr};
+use super::elf::PAGESIZE;
+use super::output::{IsCIdentifier, IsNewSegment, createPhdr, OutputEhdr, OutputShdr, ptr2ref_dyn, OutputPhdr, OutputShstrtab, OutputSymtab, OutputStrtab, OutputDynamic, OutputRelaDyn,
+    OutputInterp, OutputDynsym, OutputDynstr, OutputHash, OutputGnuHash, OutputGot, OutputGotPlt, OutputPlt, OutputRelaPlt, OutputDynbss, OutputIplt, OutputRelaIplt, OutputEhFrameHdr, GnuHash};
 use super::symbol::Symbol;
+use super::file::File;
+use super::elf::{Sym, Rela};
+use super::ehframe;
+use super::context::{UnresolvedSymbols, Icf, HashStyle};
+use super::inputsections::InputSection;
 
 pub fn ResolveSymbols(ctx: &mut Context) {
     for file in ctx.Objs.iter() {
@@ -16,6 +22,119 @@ pub fn ResolveSymbols(ctx: &mut Context) {
         }
     }
     ctx.Objs.retain(|obj| {obj.borrow().IsAlive()});
+    EliminateDuplicateComdatGroups(ctx);
+
+    // the archive members extracted above were treated as lazy during the
+    // first round. now that they are alive, their definitions may win
+    for sym in ctx.SymbolMap.values() {
+        let mut sym = sym.borrow_mut();
+        sym.File = None;
+        sym.SetInputSection(None);
+        sym.Value = 0;
+        sym.SymIdx = 0;
+        sym.Visibility = abi::STV_DEFAULT;
+    }
+    for file in ctx.Objs.iter() {
+        Objectfile::ResolveSymbols(file);
+    }
+}
+
+/// only the first copy of each COMDAT group is kept. this must be done before
+/// the final round of symbol resolution, so that the symbols defined in the
+/// discarded sections don't win
+pub fn EliminateDuplicateComdatGroups(ctx: &mut Context) {
+    for file in &ctx.Objs {
+        let groups = file.borrow().ComdatGroups.clone();
+        for (signature, members) in &groups {
+            match ctx.ComdatGroups.get(signature) {
+                None => { ctx.ComdatGroups.insert(signature.clone(), file.clone()); },
+                Some(owner) if Rc::ptr_eq(owner, file) => {},
+                Some(_) => file.borrow_mut().DiscardComdatGroup(members),
+            }
+        }
+    }
+}
+
+pub fn CheckDuplicateSymbols(ctx: &mut Context) {
+    if ctx.Args.AllowMultipleDefinition {
+        return;
+    }
+
+    let mut dups = vec![];
+    for file in &ctx.Objs {
+        // the internal file never complains, user definitions just win
+        if Rc::ptr_eq(file, &ctx.InternalObj) {
+            continue;
+        }
+        Objectfile::CollectDuplicateSymbols(file, &mut dups);
+    }
+
+    if !dups.is_empty() {
+        error!("\n{}", dups.join("\n"));
+    }
+}
+
+/// every non-weak global referenced by a live object must be defined by now
+pub fn CheckUndefinedSymbols(ctx: &mut Context) {
+    if ctx.Args.UnresolvedSymbols == UnresolvedSymbols::IgnoreAll {
+        return;
+    }
+
+    let sonames: Vec<String> = ctx.Objs.iter()
+        .filter(|file| file.borrow().IsDso())
+        .map(|file| file.borrow().Soname.clone())
+        .collect();
+
+    // symbol name -> files that reference it
+    let mut undefs: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for file in &ctx.Objs {
+        // `-u` symbols are allowed to be missing
+        if Rc::ptr_eq(file, &ctx.InternalObj) {
+            continue;
+        }
+
+        // a shared library may rely on the libraries it was linked
+        // against, so it's checked only when all of them are here
+        let obj = file.borrow();
+        let report = match obj.IsDso() {
+            true => obj.Needed.iter().all(|name| sonames.contains(name)),
+            false => ctx.Args.UnresolvedSymbols == UnresolvedSymbols::ReportAll,
+        };
+        if !report {
+            continue;
+        }
+
+        for i in obj.FirstGlobal..obj.ElfSyms.len() {
+            let esym = &obj.ElfSyms[i];
+            if !esym.IsUndef() || esym.IsWeak() || obj.IsHiddenVersion(i) {
+                continue;
+            }
+
+            let sym = obj.Symbols.get(&i).unwrap().borrow();
+            if sym.File.is_none() {
+                undefs.entry(sym.Name.clone()).or_default().push(obj.DisplayName());
+            }
+        }
+    }
+
+    if undefs.is_empty() {
+        return;
+    }
+
+    let mut msg = String::new();
+    for (name, files) in &undefs {
+        msg += &format!("\nundefined symbol: {}", name);
+        for f in files {
+            msg += &format!("\n>>> referenced by {}", f);
+        }
+    }
+
+    if ctx.Args.WarnUnresolvedSymbols {
+        warn!("{}", msg);
+    }
+    else {
+        error!("{}", msg);
+    }
 }
 
 // Common symbols are used by C's tantative definitions. Tentative
@@ -69,15 +188,125 @@ pub fn RegisterSectionPieces(ctx: &mut Context) {
     }
 }
 
-// mark
+/// symbols defined by the linker itself. crt1.o and libc's startup code need them.
+/// their values are fixed up in `FixSyntheticSymbols`
+pub const SYNTHETIC_SYMBOLS: [(&str, u8); 18] = [
+    ("__ehdr_start",            abi::STV_HIDDEN),
+    ("__executable_start",      abi::STV_DEFAULT),
+    ("__init_array_start",      abi::STV_HIDDEN),
+    ("__init_array_end",        abi::STV_HIDDEN),
+    ("__fini_array_start",      abi::STV_HIDDEN),
+    ("__fini_array_end",        abi::STV_HIDDEN),
+    ("__preinit_array_start",   abi::STV_HIDDEN),
+    ("__preinit_array_end",     abi::STV_HIDDEN),
+    ("__rela_iplt_start",       abi::STV_HIDDEN),
+    ("__rela_iplt_end",         abi::STV_HIDDEN),
+    ("__global_pointer$",       abi::STV_DEFAULT),
+    ("__bss_start",             abi::STV_DEFAULT),
+    ("_end",                    abi::STV_DEFAULT),
+    ("end",                     abi::STV_DEFAULT),
+    ("_etext",                  abi::STV_DEFAULT),
+    ("etext",                   abi::STV_DEFAULT),
+    ("_edata",                  abi::STV_DEFAULT),
+    ("edata",                   abi::STV_DEFAULT),
+];
+
+/// the internal file holds the symbols that are defined by the linker.
+/// it's put after all the input files, so real definitions take precedence
 pub fn CreateInternalFile(ctx: &mut Context) {
     let mut obj = Objectfile{
         ..Default::default()
     };
+    obj.File = Rc::new(File { Name: "<internal>".into(), ..Default::default() });
 
     obj.Symbols.insert(0, Symbol::new(""));
     obj.FirstGlobal = 1;
     obj.IsAlive = true;
+
+    // only the ones some input file refers to are defined. they are weak,
+    // so a definition in an input file wins
+    ctx.InternalEsyms = vec![Rc::new(Sym::default())];
+    for (name, vis) in SYNTHETIC_SYMBOLS {
+        if !ctx.SymbolMap.contains_key(name) {
+            continue;
+        }
+        let esym = Sym {
+            Info:   abi::STB_WEAK << 4 | abi::STT_NOTYPE,
+            Other:  vis,
+            Shndx:  abi::SHN_ABS,
+            ..Default::default()
+        };
+        obj.Symbols.insert(ctx.InternalEsyms.len(), Symbol::GetSymbolByName(ctx, name));
+        ctx.InternalEsyms.push(Rc::new(esym));
+    }
+
+    // __start_foo and __stop_foo mark the bounds of the output section foo
+    let names: Vec<String> = ctx.OutputSections.iter()
+        .map(|osec| osec.borrow().Name.clone())
+        .filter(|name| IsCIdentifier(name))
+        .flat_map(|name| [format!("__start_{}", name), format!("__stop_{}", name)])
+        .filter(|name| ctx.SymbolMap.contains_key(name))
+        .collect();
+    for name in names {
+        let esym = Sym {
+            Info:   abi::STB_WEAK << 4 | abi::STT_NOTYPE,
+            Other:  abi::STV_PROTECTED,
+            Shndx:  abi::SHN_ABS,
+            ..Default::default()
+        };
+        obj.Symbols.insert(ctx.InternalEsyms.len(), Symbol::GetSymbolByName(ctx, &name));
+        ctx.InternalEsyms.push(Rc::new(esym));
+    }
+
+    // the dynamic linker, or a static PIE, finds .dynamic through _DYNAMIC
+    if ctx.NeedsDynamic() {
+        let esym = Sym {
+            Info:   abi::STB_GLOBAL << 4 | abi::STT_NOTYPE,
+            Other:  abi::STV_HIDDEN,
+            Shndx:  abi::SHN_ABS,
+            ..Default::default()
+        };
+        obj.Symbols.insert(ctx.InternalEsyms.len(), Symbol::GetSymbolByName(ctx, "_DYNAMIC"));
+        ctx.InternalEsyms.push(Rc::new(esym));
+    }
+
+    // `-u` symbols are referenced by no one but us, so that archive
+    // members defining them are pulled in
+    for name in ctx.Args.Undefined.clone() {
+        let esym = Sym {
+            Info:   abi::STB_GLOBAL << 4 | abi::STT_NOTYPE,
+            Shndx:  abi::SHN_UNDEF,
+            ..Default::default()
+        };
+        obj.Symbols.insert(ctx.InternalEsyms.len(), Symbol::GetSymbolByName(ctx, &name));
+        ctx.InternalEsyms.push(Rc::new(esym));
+    }
+
+    // symbols assigned by the linker script. PROVIDE ones are defined only if someone mentions them
+    let mut assigned: Vec<(String, bool, bool)> = vec![];
+    if let Some(script) = &ctx.Script {
+        for a in script.SymbolNames() {
+            let provided = a.Provide && !ctx.SymbolMap.contains_key(&a.Name);
+            if !provided && !assigned.iter().any(|(name, _, _)| *name == a.Name) {
+                assigned.push((a.Name.clone(), a.Provide, a.Hidden));
+            }
+        }
+    }
+    for (name, provide, hidden) in assigned {
+        let sym = Symbol::GetSymbolByName(ctx, &name);
+        if obj.Symbols.values().any(|s| Rc::ptr_eq(s, &sym)) {
+            continue;
+        }
+        let bind = if provide { abi::STB_WEAK } else { abi::STB_GLOBAL };
+        let esym = Sym {
+            Info:   bind << 4 | abi::STT_NOTYPE,
+            Other:  if hidden { abi::STV_HIDDEN } else { abi::STV_DEFAULT },
+            Shndx:  abi::SHN_ABS,
+            ..Default::default()
+        };
+        obj.Symbols.insert(ctx.InternalEsyms.len(), sym);
+        ctx.InternalEsyms.push(Rc::new(esym));
+    }
     obj.ElfSyms = ctx.InternalEsyms.clone();
 
     let o = obj.ToRcRefcell();
@@ -86,60 +315,300 @@ pub fn CreateInternalFile(ctx: &mut Context) {
     ctx.Objs.push(o);
 }
 
+/// assign addresses to the symbols in the internal file,
+/// unless some input file has defined them
+pub fn FixSyntheticSymbols(ctx: &mut Context) {
+    struct Range { Name: String, Flags: u64, IsNobits: bool, Start: u64, End: u64 }
+
+    // .tbss takes no space in the image
+    let mut chunks: Vec<Range> = vec![];
+    for c in &ctx.Chunks {
+        let c = ptr2ref_dyn(*c);
+        if c.GetShdr().Flags & abi::SHF_ALLOC as u64 == 0 || isTbss(c) {
+            continue;
+        }
+        let shdr = c.GetShdr().clone();
+        chunks.push(Range {
+            Name: c.GetName().clone(),
+            Flags: shdr.Flags,
+            IsNobits: shdr.Type == abi::SHT_NOBITS,
+            Start: shdr.Addr,
+            End: shdr.Addr + shdr.Size as u64,
+        });
+    }
+    let find = |name: &str| chunks.iter().find(|c| c.Name == name);
+    let isWritable = |c: &&Range| c.Flags & abi::SHF_WRITE as u64 != 0;
+
+    // the bounds of an absent section are None, and become an absolute 0
+    let mut values: Vec<(String, Option<u64>)> = vec![];
+    let ehdr = chunks.first().map(|c| c.Start);
+    values.push(("__ehdr_start".into(), ehdr));
+    values.push(("__executable_start".into(), ehdr));
+
+    // the gp register can reach [gp - 2048, gp + 2047]
+    let gp = match find(".sdata") {
+        Some(c) => Some(c.Start + 0x800),
+        None => chunks.iter().find(isWritable).map(|c| c.Start + 0x800)
+    };
+    values.push(("__global_pointer$".into(), gp));
+
+    let textEnd = chunks.iter()
+        .filter(|c| c.Flags & abi::SHF_EXECINSTR as u64 != 0)
+        .map(|c| c.End).max();
+    let dataEnd = chunks.iter()
+        .filter(|c| !c.IsNobits)
+        .map(|c| c.End).max();
+    let imageEnd = chunks.iter().map(|c| c.End).max();
+    let bssStart = chunks.iter().find(|c| c.IsNobits).map_or(dataEnd, |c| Some(c.Start));
+
+    for (name, val) in [
+        ("_etext", textEnd), ("etext", textEnd),
+        ("_edata", dataEnd), ("edata", dataEnd),
+        ("__bss_start", bssStart),
+        ("_end", imageEnd), ("end", imageEnd),
+    ] {
+        values.push((name.into(), val));
+    }
+
+    if ctx.NeedsDynamic() {
+        values.push(("_DYNAMIC".into(), find(".dynamic").map(|c| c.Start)));
+    }
+
+    for (name, start, end) in SectionBoundSymbols(ctx) {
+        let c = find(&name);
+        values.push((start, c.map(|c| c.Start)));
+        values.push((end, c.map(|c| c.End)));
+    }
+
+    // the script has the last word
+    if let Some(script) = &ctx.Script {
+        values.extend(script.Values.iter().map(|(name, val)| (name.clone(), Some(*val))));
+    }
+
+    for (name, val) in values {
+        SetSyntheticSymbol(ctx, &name, val);
+    }
+    ctx.GpAddr = ctx.SymbolMap.get("__global_pointer$").map_or(0, |s| s.borrow().GetAddr());
+}
+
+/// (section, start symbol, end symbol) of the sections whose bounds the linker defines.
+/// __start_foo and __stop_foo are the ones of the output section foo
+fn SectionBoundSymbols(ctx: &Context) -> Vec<(String, String, String)> {
+    let mut bounds: Vec<(String, String, String)> = ["init_array", "fini_array", "preinit_array"].iter()
+        .map(|array| (format!(".{}", array), format!("__{}_start", array), format!("__{}_end", array)))
+        .collect();
+    bounds.push((".rela.iplt".into(), "__rela_iplt_start".into(), "__rela_iplt_end".into()));
+    for osec in &ctx.OutputSections {
+        let name = &osec.borrow().Name;
+        if IsCIdentifier(name) {
+            bounds.push((name.clone(), format!("__start_{}", name), format!("__stop_{}", name)));
+        }
+    }
+    bounds
+}
+
+/// the bounds of an absent section are an absolute 0, which a PIE must not
+/// relocate. so it's known before `ScanRelocations`. .rela.iplt is created
+/// later, but only for position dependent outputs, where it doesn't matter
+pub fn MarkAbsentSectionBounds(ctx: &mut Context) {
+    for (name, start, end) in SectionBoundSymbols(ctx) {
+        let present = ctx.Chunks.iter().any(|c| *ptr2ref_dyn(*c).GetName() == name);
+        for name in [start, end] {
+            if let Some(sym) = ctx.SymbolMap.get(&name) {
+                let mut sym = sym.borrow_mut();
+                if sym.File.as_ref().is_some_and(|f| Rc::ptr_eq(f, &ctx.InternalObj)) {
+                    sym.IsAbsValue = !present;
+                }
+            }
+        }
+    }
+}
+
+/// only touch the symbol if it's still owned by the internal file
+pub fn SetSyntheticSymbol(ctx: &Context, name: &str, val: Option<u64>) {
+    if let Some(sym) = ctx.SymbolMap.get(name) {
+        let mut sym = sym.borrow_mut();
+        if let Some(file) = &sym.File {
+            if Rc::ptr_eq(file, &ctx.InternalObj) {
+                sym.SetInputSection(None);
+                sym.Value = val.unwrap_or(0);
+                sym.IsAbsValue = val.is_none();
+            }
+        }
+    }
+}
+
 // mark
 pub fn CreateSyntheticSections(ctx: &mut Context) {
     ctx.Ehdr = OutputEhdr::new();
     ctx.Phdr = OutputPhdr::new();
     ctx.Shdr = OutputShdr::new();
+    // the script decides what's in memory, and it's not the headers
+    if ctx.Script.is_some() {
+        ctx.Ehdr.Chunk.Shdr.Flags = 0;
+        ctx.Phdr.Shdr.Flags = 0;
+    }
 
     // ehdr must be the first chunk to be written
     ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.Ehdr));
     ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.Phdr));
     // the first section header is always empty.(according to the abi)
     ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.Shdr));
+
+    ctx.Shstrtab = OutputShstrtab::new();
+    ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.Shstrtab));
+
+    if ctx.NeedsDynamic() {
+        ctx.Dynamic = OutputDynamic::new();
+        ctx.RelaDyn = OutputRelaDyn::new();
+        ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.Dynamic));
+        ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.RelaDyn));
+        // the dynamic linker reads DT_SYMTAB even when there is nothing to import
+        ctx.Dynsym = OutputDynsym::new();
+        ctx.Dynstr = OutputDynstr::new();
+        ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.Dynsym));
+        ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.Dynstr));
+    }
+
+    ctx.EhFrameHdr = OutputEhFrameHdr::new();
+    if ctx.Args.EhFrameHdr && !ehframe::EhFrameSections(ctx).is_empty() {
+        ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.EhFrameHdr));
+    }
+
+    // the ones that may be empty are registered by `ScanRelocations`
+    ctx.Got = OutputGot::new();
+    ctx.Iplt = OutputIplt::new();
+    ctx.RelaIplt = OutputRelaIplt::new();
+    if ctx.NeedsInterp() {
+        ctx.Interp = OutputInterp::new();
+        ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.Interp));
+    }
+    if ctx.IsDynamic() {
+        if ctx.Args.HashStyle != HashStyle::Gnu {
+            ctx.Hash = OutputHash::new();
+            ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.Hash));
+        }
+        if ctx.Args.HashStyle != HashStyle::Sysv {
+            ctx.GnuHash = OutputGnuHash::new();
+            ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.GnuHash));
+        }
+        ctx.GotPlt = OutputGotPlt::new();
+        ctx.Plt = OutputPlt::new();
+        ctx.RelaPlt = OutputRelaPlt::new();
+        ctx.Dynbss = OutputDynbss::new();
+    }
+
+    if !ctx.Args.StripAll {
+        ctx.Symtab = OutputSymtab::new();
+        ctx.Strtab = OutputStrtab::new();
+        ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.Symtab));
+        ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.Strtab));
+    }
 }
 
+/// assign addresses and file offsets to the chunks. returns the file size.
+/// the number of program headers depends on the layout, and when the table
+/// is loaded, the layout depends on its size. so repeat until it settles
 pub fn SetOutputSectionOffsets(ctx: &mut Context) -> usize {
-    let mut addr = IMAGE_BASE;
-    // set up addr
+    AlignTlsTemplate(ctx);
+    loop {
+        match ctx.Script.take() {
+            Some(mut script) => {
+                script.AssignAddresses(ctx);
+                ctx.Script = Some(script);
+            },
+            None => SetOutputSectionAddrs(ctx),
+        }
+
+        let fileoff = SetFileOffsets(ctx);
+        let size = createPhdr(ctx).len() * PHDR_SIZE;
+        if size == ctx.Phdr.Shdr.Size {
+            return fileoff;
+        }
+        ctx.Phdr.Shdr.Size = size;
+    }
+}
+
+/// the TLS chunks(.tdata, then .tbss) make up the TLS template, which is
+/// copied to where tp points for each thread. tp is aligned to PT_TLS's
+/// p_align, so the template has to start at such an address as well,
+/// or the variables' offsets from tp would break their alignment
+fn AlignTlsTemplate(ctx: &mut Context) {
+    let tls: Vec<*mut dyn Chunker> = ctx.Chunks.iter().copied()
+        .filter(|c| ptr2ref_dyn(*c).isTls()).collect();
+    let align = tls.iter().map(|c| ptr2ref_dyn(*c).GetShdr().AddrAlign).max().unwrap_or(1);
+    if let Some(first) = tls.first() {
+        let shdr = ptr2ref_dyn(*first).GetShdr();
+        shdr.AddrAlign = shdr.AddrAlign.max(align);
+    }
+}
+
+/// chunks are placed one after another from the image base. a chunk with
+/// its address given by `--section-start` moves the following ones as well.
+/// .tbss chunks only take space in the TLS template, right after .tdata
+fn SetOutputSectionAddrs(ctx: &mut Context) {
+    let mut addr = ctx.Args.ImageBase as usize;
+    let mut tbssEnd = 0;
     for c in &ctx.Chunks {
         let c = ptr2ref_dyn(*c);
         if c.GetShdr().Flags & abi::SHF_ALLOC as u64 == 0 {
             continue;
         }
 
-        addr = AlignTo(addr, c.GetShdr().AddrAlign as usize);
-        c.GetShdr().Addr = addr as u64;
-
-        if !isTbss(c) {
-            addr += c.GetShdr().Size;
+        if isTbss(c) {
+            let start = AlignTo(addr.max(tbssEnd), c.GetShdr().AddrAlign as usize);
+            c.GetShdr().Addr = start as u64;
+            tbssEnd = start + c.GetShdr().Size;
+            continue;
         }
+
+        addr = match ctx.Args.SectionStart.get(c.GetName()) {
+            Some(start) => *start as usize,
+            None => AlignTo(addr, c.GetShdr().AddrAlign as usize)
+        };
+        c.GetShdr().Addr = addr as u64;
+        addr += c.GetShdr().Size;
     }
+}
 
-    let mut i = 0;
-    let first = ptr2ref_dyn(ctx.Chunks[0]);
-    // set up offset
-    loop {
-        let shdr = ptr2ref_dyn(ctx.Chunks[i]).GetShdr();
-        shdr.Offset = (shdr.Addr - first.GetShdr().Addr) as usize;
-        i += 1;
+/// a chunk keeps its distance to the previous one in the same segment.
+/// each segment starts at an offset congruent to its address modulo the page size,
+/// so that it can be mmap'ed. non-alloc chunks just follow
+fn SetFileOffsets(ctx: &mut Context) -> usize {
+    let mut fileoff = 0;
+    let mut prev: Option<*mut dyn Chunker> = None;
+    for i in 0..ctx.Chunks.len() {
+        let c = ptr2ref_dyn(ctx.Chunks[i]);
+        let shdr = c.GetShdr().clone();
 
-        if i >= ctx.Chunks.len() || 
-            ptr2ref_dyn(ctx.Chunks[i]).GetShdr().Flags & abi::SHF_ALLOC as u64 == 0 {
-            break;
+        if shdr.Flags & abi::SHF_ALLOC as u64 == 0 {
+            fileoff = AlignTo(fileoff, shdr.AddrAlign as usize);
+            c.GetShdr().Offset = fileoff;
+            fileoff += shdr.Size;
+            continue;
         }
-    }
 
-    let lastShdr = ptr2ref_dyn(ctx.Chunks[i-1]).GetShdr();
-    let mut fileoff = lastShdr.Offset + lastShdr.Size;
+        let offset = match prev {
+            Some(p) if !IsNewSegment(ctx, p, ctx.Chunks[i]) => {
+                let p = ptr2ref_dyn(p).GetShdr();
+                p.Offset + (shdr.Addr - p.Addr) as usize
+            },
+            _ => {
+                let page = PAGESIZE as usize;
+                let off = fileoff / page * page + shdr.Addr as usize % page;
+                if off < fileoff { off + page } else { off }
+            }
+        };
+        c.GetShdr().Offset = offset;
 
-    // non-alloc sections 
-    while i < ctx.Chunks.len() {
-        let shdr = ptr2ref_dyn(ctx.Chunks[i]).GetShdr();
-        fileoff = AlignTo(fileoff, shdr.AddrAlign as usize);
-        shdr.Offset = fileoff;
-        fileoff += shdr.Size;
-        i += 1;
+        // .tbss is not part of any PT_LOAD
+        if isTbss(c) {
+            continue;
+        }
+        prev = Some(ctx.Chunks[i]);
+        if shdr.Type != abi::SHT_NOBITS {
+            fileoff = fileoff.max(offset + shdr.Size);
+        }
     }
     fileoff
 }
@@ -162,6 +631,12 @@ pub fn BinSections(ctx: &mut Context) {
             }
         }
     }
+
+    if let Some(script) = &ctx.Script {
+        for osec in &ctx.OutputSections {
+            script.SortMembers(&mut osec.borrow_mut().Members);
+        }
+    }
 }
 
 //pub fn CollectOutputSections(ctx: &mut Context) -> Vec<Rc<RefCell<dyn Chunker>>>{
@@ -184,6 +659,317 @@ pub fn CollectOutputSections(ctx: &mut Context) -> Vec<*mut dyn Chunker>{
     osecs
 }
 
+/// decide what the relocations need at runtime. with `-pie`, absolute pointers
+/// to the image become R_RISCV_RELATIVE in .rela.dyn, and code using absolute
+/// addresses can't be fixed this way, so it's an error. symbols imported from
+/// shared libraries are reached through the GOT and the PLT, or copied into
+/// .dynbss if non-PIC code refers to their data directly. PIC code loads
+/// addresses from the GOT, which is needed even in static links
+pub fn ScanRelocations(ctx: &mut Context) {
+    let dynamic = ctx.IsDynamic();
+
+    let mut relocs = vec![];
+    let mut got = vec![];
+    let mut plt = vec![];
+    let mut iplt = vec![];
+    let mut copyrel = vec![];
+    let mut dynsyms: Vec<Rc<RefCell<Symbol>>> = vec![];
+    let mut addDynsym = |sym: &Rc<RefCell<Symbol>>| {
+        if !dynsyms.iter().any(|s| Rc::ptr_eq(s, sym)) {
+            dynsyms.push(sym.clone());
+        }
+    };
+
+    for obj in &ctx.Objs {
+        let o = obj.borrow();
+        for isec in o.Sections.iter().flatten() {
+            let i = isec.borrow();
+            if !i.IsAlive || i.Shdr().Flags & abi::SHF_ALLOC as u64 == 0 {
+                continue;
+            }
+            let writable = i.Shdr().Flags & abi::SHF_WRITE as u64 != 0;
+
+            for (idx, rel) in i.Rels.iter().enumerate() {
+                let rc = o.Symbols.get(&(rel.Sym as usize)).unwrap();
+                let mut sym = rc.borrow_mut();
+                // the GOT slot holds what the resolver returns
+                if sym.IsIfunc() && sym.IpltIdx.is_none() {
+                    sym.IpltIdx = Some(iplt.len());
+                    iplt.push(rc.clone());
+                    if sym.GotIdx.is_none() {
+                        sym.GotIdx = Some(got.len());
+                        got.push(rc.clone());
+                    }
+                }
+
+                let imported = sym.IsImported();
+                if imported {
+                    addDynsym(rc);
+                }
+                // undefined weak symbols stay 0, and absolute ones don't move.
+                // the symbols defined by us are addresses in the image, unless
+                // they are the bounds of an absent section
+                let isAbsolute = !i.RelFragments.contains_key(&idx) && sym.IsAbsolute(ctx);
+
+                let mut needsPlt = false;
+                // the address is taken directly. an imported function is then at
+                // its PLT entry, and imported data has to be copied into .dynbss
+                let mut takesAddr = false;
+                match rel.Type {
+                    // general dynamic TLS accesses become initial exec ones for imported variables
+                    abi::R_RISCV_GOT_HI20 | abi::R_RISCV_TLS_GOT_HI20 | abi::R_RISCV_TLS_GD_HI20
+                        if sym.GotIdx.is_none() && (rel.Type != abi::R_RISCV_TLS_GD_HI20 || imported) => {
+                        sym.GotIdx = Some(got.len());
+                        got.push(rc.clone());
+                    },
+                    abi::R_RISCV_TPREL_HI20 | abi::R_RISCV_TPREL_LO12_I | abi::R_RISCV_TPREL_LO12_S if imported => {
+                        error!("{}: relocation type {} against '{}' defined in a shared library; recompile with -ftls-model=initial-exec",
+                            o.File.DisplayName(), rel.Type, sym.Name);
+                    },
+                    abi::R_RISCV_CALL | abi::R_RISCV_CALL_PLT | abi::R_RISCV_JAL => {
+                        needsPlt = imported && i.GetTlsGdReg(rel, &sym).is_none();
+                    },
+                    abi::R_RISCV_64 => {
+                        if isAbsolute || (!imported && !ctx.Args.Pie) {
+                            continue;
+                        }
+                        if writable {
+                            relocs.push((isec.clone(), idx));
+                        }
+                        else if imported && !ctx.Args.Pie {
+                            takesAddr = true;
+                        }
+                        else {
+                            error!("{}: relocation R_RISCV_64 against '{}' in read-only section '{}'; recompile with {}",
+                                o.File.DisplayName(), sym.Name, i.Name(), if imported { "-fPIC" } else { "-fPIE" });
+                        }
+                    },
+                    abi::R_RISCV_32 | abi::R_RISCV_HI20 | abi::R_RISCV_LO12_I | abi::R_RISCV_LO12_S | abi::R_RISCV_PCREL_HI20 => {
+                        if ctx.Args.Pie && rel.Type != abi::R_RISCV_PCREL_HI20 && !isAbsolute {
+                            error!("{}: relocation type {} against '{}' can not be used when making a PIE; recompile with -fPIE",
+                                o.File.DisplayName(), rel.Type, sym.Name);
+                        }
+                        takesAddr = imported;
+                    },
+                    _ => {}
+                }
+
+                if takesAddr {
+                    if sym.ElfSym().Type() == abi::STT_FUNC {
+                        sym.IsCanonical = true;
+                        needsPlt = true;
+                    }
+                    else if !copyrel.iter().any(|s| Rc::ptr_eq(s, rc)) {
+                        copyrel.push(rc.clone());
+                    }
+                }
+                if needsPlt && sym.PltIdx.is_none() {
+                    sym.PltIdx = Some(plt.len());
+                    plt.push(rc.clone());
+                }
+            }
+        }
+    }
+
+    // relative ones go first, as DT_RELACOUNT counts them from the beginning
+    ctx.RelaDyn.Relocs = relocs;
+    ctx.Got.Symbols = got;
+    if !ctx.Got.Symbols.is_empty() {
+        ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.Got));
+    }
+    // dynamic outputs have the R_RISCV_IRELATIVE in .rela.dyn instead
+    ctx.Iplt.Symbols = iplt;
+    if !ctx.Iplt.Symbols.is_empty() {
+        ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.Iplt));
+        if !ctx.NeedsDynamic() {
+            ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.RelaIplt));
+        }
+    }
+    if !dynamic {
+        return;
+    }
+
+    PlaceCopyRelocations(ctx, copyrel, &mut addDynsym);
+    for sym in CollectExportedSymbols(ctx) {
+        addDynsym(&sym);
+    }
+
+    ctx.Plt.Symbols = plt;
+    if !ctx.Plt.Symbols.is_empty() {
+        ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.Plt));
+        ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.GotPlt));
+        ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.RelaPlt));
+    }
+    if !ctx.Dynbss.Symbols.is_empty() {
+        ctx.Chunks.push(std::ptr::addr_of_mut!(*ctx.Dynbss));
+    }
+
+    SetDynamicSymbols(ctx, dynsyms);
+}
+
+/// give each copied symbol its place in .dynbss. the other names of the
+/// same data in the shared library(e.g. `environ` and `__environ`) share
+/// the place, otherwise they would refer to the original one
+fn PlaceCopyRelocations(ctx: &mut Context, syms: Vec<Rc<RefCell<Symbol>>>, addDynsym: &mut impl FnMut(&Rc<RefCell<Symbol>>)) {
+    let mut offset = 0;
+    for sym in syms {
+        if sym.borrow().HasCopyrel {
+            continue;
+        }
+
+        let (dso, idx) = {
+            let s = sym.borrow();
+            (s.File.clone().unwrap(), s.SymIdx)
+        };
+        let dso = dso.borrow();
+        let esym = &dso.ElfSyms[idx];
+        let shdr = &dso.ElfSections[esym.Shndx as usize];
+        let align = shdr.AddrAlign.max(1).min(1 << esym.Val.trailing_zeros().min(63));
+        offset = AlignTo(offset, align as usize);
+        ctx.Dynbss.Shdr.AddrAlign = ctx.Dynbss.Shdr.AddrAlign.max(align);
+
+        for i in dso.FirstGlobal..dso.ElfSyms.len() {
+            let e = &dso.ElfSyms[i];
+            if e.IsUndef() || e.Val != esym.Val || e.Shndx != esym.Shndx {
+                continue;
+            }
+            let alias = dso.Symbols.get(&i).unwrap();
+            let owned = alias.borrow().SymIdx == i && alias.borrow().File.as_ref().is_some_and(|f| std::ptr::eq(f.as_ptr(), &*dso));
+            if owned && !alias.borrow().HasCopyrel {
+                alias.borrow_mut().HasCopyrel = true;
+                ctx.Dynbss.Symbols.push((alias.clone(), offset));
+                addDynsym(alias);
+            }
+        }
+        offset += esym.Size as usize;
+    }
+    ctx.Dynbss.Shdr.Size = offset;
+}
+
+/// symbols defined by us that shared libraries may refer to. all of them with `--export-dynamic`
+fn CollectExportedSymbols(ctx: &Context) -> Vec<Rc<RefCell<Symbol>>> {
+    let mut referenced: BTreeSet<String> = BTreeSet::new();
+    for file in &ctx.Objs {
+        let obj = file.borrow();
+        if !obj.IsDso() {
+            continue;
+        }
+        for i in obj.FirstGlobal..obj.ElfSyms.len() {
+            if obj.ElfSyms[i].IsUndef() {
+                referenced.insert(obj.Symbols.get(&i).unwrap().borrow().Name.clone());
+            }
+        }
+    }
+
+    ctx.SymbolMap.values().filter(|sym| {
+        let s = sym.borrow();
+        let defined = match &s.File {
+            Some(file) => !file.borrow().IsDso() && !Rc::ptr_eq(file, &ctx.InternalObj),
+            None => false
+        };
+        defined && !s.IsHidden() && OutputSymtab::ShouldWrite(&s, &s.ElfSym()) &&
+            (ctx.Args.ExportDynamic || referenced.contains(&s.Name))
+    }).cloned().collect()
+}
+
+/// imported symbols come first, then the defined ones sorted by their
+/// .gnu.hash buckets. the sonames needed go to .dynstr as well
+fn SetDynamicSymbols(ctx: &mut Context, syms: Vec<Rc<RefCell<Symbol>>>) {
+    let (imports, mut exports): (Vec<_>, Vec<_>) = syms.into_iter()
+        .partition(|sym| sym.borrow().IsImported() && !sym.borrow().HasCopyrel);
+
+    let numBuckets = exports.len() / 8 + 1;
+    exports.sort_by_key(|sym| GnuHash(&sym.borrow().Name) as usize % numBuckets);
+    ctx.GnuHash.NumBuckets = numBuckets;
+    ctx.GnuHash.SymOffset = imports.len() + 1;
+    ctx.GnuHash.BloomSize = (exports.len() * 12 / 64).max(1).next_power_of_two();
+
+    let mut needed = vec![];
+    for file in &ctx.Objs {
+        let obj = file.borrow();
+        let used = || imports.iter().chain(&exports)
+            .any(|sym| sym.borrow().File.as_ref().is_some_and(|f| Rc::ptr_eq(f, file)));
+        if obj.IsDso() && (!obj.AsNeeded || used()) {
+            needed.push(ctx.Dynstr.Add(&obj.Soname));
+        }
+    }
+    ctx.Dynamic.Needed = needed;
+
+    ctx.Dynsym.Symbols = imports.into_iter().chain(exports).collect();
+    ctx.Dynsym.NameOffsets.clear();
+    for (i, sym) in ctx.Dynsym.Symbols.iter().enumerate() {
+        let mut sym = sym.borrow_mut();
+        sym.DynsymIdx = Some(i + 1);
+        ctx.Dynsym.NameOffsets.push(ctx.Dynstr.Add(&sym.Name));
+    }
+}
+
+/// imported symbols are at their PLT entries or in .dynbss, and IFUNC
+/// symbols at their .iplt entries, as far as the output is concerned
+pub fn SetPltSymbolAddrs(ctx: &mut Context) {
+    for sym in &ctx.Dynsym.Symbols {
+        let mut sym = sym.borrow_mut();
+        if !sym.IsImported() {
+            continue;
+        }
+        sym.Value = match sym.PltIdx {
+            Some(_) => sym.GetPltAddr(ctx),
+            None => 0
+        };
+    }
+    for (sym, offset) in &ctx.Dynbss.Symbols {
+        sym.borrow_mut().Value = ctx.Dynbss.Shdr.Addr + *offset as u64;
+    }
+    for (i, sym) in ctx.Iplt.Symbols.iter().enumerate() {
+        sym.borrow_mut().IpltAddr = ctx.Iplt.Shdr.Addr + (i * OutputIplt::ENTRY_SIZE) as u64;
+    }
+}
+
+/// shrink code sequences marked by R_RISCV_RELAX(unless `--no-relax`), and
+/// delete the surplus nops of R_RISCV_ALIGN. sections get smaller, so their
+/// layout has to be computed again after this
+pub fn RelaxSections(ctx: &mut Context) {
+    let mut sections = vec![];
+    for obj in &ctx.Objs {
+        let o = obj.borrow();
+        if !o.IsAlive || std::ptr::eq(obj.as_ptr(), ctx.InternalObj.as_ptr()) {
+            continue;
+        }
+
+        let rvc = o.GetEhdr().Flags & abi::EF_RISCV_RVC != 0;
+        for isec in o.Sections.iter().flatten() {
+            let i = isec.borrow();
+            if i.IsAlive && !i.Rels.is_empty() && i.Shdr().Flags & abi::SHF_EXECINSTR as u64 != 0 {
+                sections.push((isec.clone(), rvc));
+            }
+        }
+    }
+
+    // nothing moves farther than this
+    let margin = sections.iter().map(|(isec, _)| isec.borrow().MaxDeletion()).sum();
+    for (isec, rvc) in sections {
+        let (deltas, relaxations) = isec.borrow().Relax(ctx.GpAddr, margin, rvc, !ctx.Args.NoRelax);
+        let mut isec = isec.borrow_mut();
+        isec.ShSize -= deltas.last().map_or(0, |d| d.1) as usize;
+        isec.Deltas = deltas;
+        isec.Relaxations = relaxations;
+    }
+
+    // symbols defined after the deleted bytes move backward
+    for obj in &ctx.Objs {
+        for sym in obj.borrow().Symbols.values() {
+            let mut sym = sym.borrow_mut();
+            let owned = sym.File.as_ref().is_some_and(|f| Rc::ptr_eq(f, obj));
+            if !owned {
+                continue;
+            }
+            if let Some(isec) = sym.InputSection.clone() {
+                sym.Value -= isec.borrow().GetDelta(sym.Value);
+            }
+        }
+    }
+}
+
 pub fn ComputeSectionSizes(ctx: &mut Context) {
     for osec in &ctx.OutputSections {
         let mut offset = 0;
@@ -225,7 +1011,7 @@ pub fn SortOutputSections(ctx: &mut Context) {
         if std::ptr::eq(std::ptr::addr_of!(*c) as *const OutputEhdr, eptr) {
             return 0;
         }
-        if ty == abi::SHT_NOTE {
+        if ty == abi::SHT_NOTE || std::ptr::eq(std::ptr::addr_of!(*c) as *const OutputInterp, std::ptr::addr_of!(*ctx.Interp)) {
             return 2;
         }
         let b2i = |b: bool| -> u32 {
@@ -243,9 +1029,79 @@ pub fn SortOutputSections(ctx: &mut Context) {
         return writeable << 7 | notExec << 6 | notTls << 5 | isBss << 4;
     };
 
-    ctx.Chunks.sort_by_key(|c| {
-        unsafe {rank(&mut **c)}
-    })
+    if ctx.Script.is_none() {
+        ctx.Chunks.sort_by_key(|c| {
+            unsafe {rank(&mut **c)}
+        });
+        return;
+    }
+
+    // the headers, then the sections in the order of SECTIONS. the ones not
+    // mentioned by the script(orphans) go after the last section of the same
+    // kind, or after all of them
+    let names = ctx.Script.as_ref().unwrap().OutputSectionNames();
+    let isHeader = |c: *mut dyn Chunker| {
+        std::ptr::eq(c as *const u8, std::ptr::addr_of!(*ctx.Ehdr) as *const u8) ||
+            std::ptr::eq(c as *const u8, std::ptr::addr_of!(*ctx.Phdr) as *const u8)
+    };
+    let isMerged = |c: *mut dyn Chunker| {
+        ctx.MergedSections.iter().any(|m| std::ptr::eq(c as *const u8, m.as_ptr() as *const u8))
+    };
+    let kind = |c: *mut dyn Chunker| (ptr2ref_dyn(c).toPhdrFlags(), ptr2ref_dyn(c).isBss());
+
+    let mut kinds: Vec<((u32, bool), usize)> = vec![];
+    for c in &ctx.Chunks {
+        if let Some(pos) = names.iter().position(|n| n == ptr2ref_dyn(*c).GetName()) {
+            kinds.push((kind(*c), pos));
+        }
+    }
+
+    let keys: Vec<(usize, usize, u32)> = ctx.Chunks.iter().map(|ptr| {
+        let c = ptr2ref_dyn(*ptr);
+        let r = rank(c);
+        if isHeader(*ptr) {
+            return (0, r as usize, 0);
+        }
+        if r >= u32::MAX - 1 {
+            return (3, 0, r);
+        }
+        if let Some(pos) = names.iter().position(|n| n == c.GetName()) {
+            return (1, pos, isMerged(*ptr) as u32);
+        }
+        let k = kind(*ptr);
+        match kinds.iter().filter(|(kk, _)| *kk == k).map(|(_, pos)| *pos).max() {
+            Some(pos) => (1, pos, 2 + r),
+            None => (2, 0, r),
+        }
+    }).collect();
+
+    let mut chunks: Vec<(*mut dyn Chunker, (usize, usize, u32))> = ctx.Chunks.iter().copied().zip(keys).collect();
+    chunks.sort_by_key(|(_, key)| *key);
+    ctx.Chunks = chunks.into_iter().map(|(c, _)| c).collect();
+}
+
+/// report failed ASSERTs and overflowed memory regions of the linker script.
+/// only the final layout matters
+pub fn CheckLayout(ctx: &mut Context) {
+    if let Some(script) = &ctx.Script {
+        if !script.Errors.is_empty() {
+            error!("{}: {}", script.Path, script.Errors.join("\n"));
+        }
+    }
+}
+
+/// give every section a index in the section header table.
+/// the ehdr, phdr and shdr are not sections, and they have no names
+pub fn SetSectionIndices(ctx: &mut Context) {
+    let mut shndx = 1;
+    for c in &ctx.Chunks {
+        let c = ptr2ref_dyn(*c);
+        if c.GetName().is_empty() {
+            continue;
+        }
+        c.SetShndx(shndx);
+        shndx += 1;
+    }
 }
 
 pub fn isTbss(chunk: &mut dyn Chunker) -> bool {
@@ -253,6 +1109,317 @@ pub fn isTbss(chunk: &mut dyn Chunker) -> bool {
     shdr.Type == abi::SHT_NOBITS && shdr.Flags & abi::SHF_TLS as u64 != 0
 }
 
+/// identical CIEs are written only once, and the FDEs of the sections removed
+/// by gc, icf or COMDAT groups are dropped. this decides the sizes of .eh_frame
+pub fn CombineEhFrames(ctx: &mut Context) {
+    let mut leaders: BTreeMap<ehframe::CieKey, (Rc<RefCell<InputSection>>, usize)> = BTreeMap::new();
+    for isec in ehframe::EhFrameSections(ctx) {
+        let keys = isec.borrow().CieKeys();
+        let mut i = isec.borrow_mut();
+        let eh = i.EhFrame.as_mut().unwrap();
+        let mut offset = 0;
+        for (idx, key) in keys.into_iter().enumerate() {
+            let cie = &mut eh.Cies[idx];
+            match leaders.get(&key) {
+                Some(leader) => cie.Leader = Some(leader.clone()),
+                None => {
+                    leaders.insert(key, (isec.clone(), idx));
+                    cie.OutputOffset = offset;
+                    offset += cie.Size;
+                }
+            }
+        }
+
+        for fde in &mut eh.Fdes {
+            fde.IsAlive = fde.Target.as_ref().is_some_and(|t| t.borrow().IsAlive);
+            if fde.IsAlive {
+                fde.OutputOffset = offset;
+                offset += fde.Size;
+            }
+        }
+
+        if eh.Terminator {
+            offset += 4;
+        }
+        i.ShSize = offset;
+        // the records of all the files must be packed, as a gap
+        // between them would be read as the terminator
+        i.P2Align = i.P2Align.min(2);
+    }
+}
+
+/// `--gc-sections`. sections that can't be reached from the roots through
+/// relocations are removed
+pub fn GcSections(ctx: &mut Context) {
+    // names referenced by object files, for __start_/__stop_ symbols
+    let mut referenced: BTreeSet<String> = BTreeSet::new();
+    for file in &ctx.Objs {
+        let obj = file.borrow();
+        for i in obj.FirstGlobal..obj.ElfSyms.len() {
+            if obj.ElfSyms[i].IsUndef() {
+                referenced.insert(obj.Symbols.get(&i).unwrap().borrow().Name.clone());
+            }
+        }
+    }
+
+    // IsAlive is used as the mark bit. sections dead before gc(the
+    // mergeable ones) are not candidates, and never get marked
+    let mut candidates = vec![];
+    let mut roots = vec![];
+    for file in &ctx.Objs {
+        for isec in file.borrow().Sections.iter().flatten() {
+            if !isec.borrow().IsAlive {
+                continue;
+            }
+            candidates.push(isec.clone());
+
+            let mut i = isec.borrow_mut();
+            let shdr = i.Shdr().clone();
+            let name = i.Name();
+            let osecName = i.OutputSection.borrow().Name.clone();
+            i.IsAlive = false;
+
+            // non-alloc sections are always kept, but they don't keep anything alive
+            if shdr.Flags & abi::SHF_ALLOC as u64 == 0 || name == ".eh_frame" {
+                i.IsAlive = true;
+                continue;
+            }
+
+            let isRoot = [abi::SHT_NOTE, abi::SHT_INIT_ARRAY, abi::SHT_FINI_ARRAY, abi::SHT_PREINIT_ARRAY].contains(&shdr.Type)
+                || [".init", ".fini", ".ctors", ".dtors", ".init_array", ".fini_array", ".preinit_array"].iter()
+                    .any(|prefix| name == *prefix || name.starts_with(&(prefix.to_string() + ".")))
+                || (IsCIdentifier(&osecName) &&
+                    (referenced.contains(&format!("__start_{}", osecName)) || referenced.contains(&format!("__stop_{}", osecName))));
+            if isRoot || i.Keep {
+                i.IsAlive = true;
+                roots.push(isec.clone());
+            }
+        }
+    }
+
+    // the entry point and `-u` symbols, and the ones shared libraries may refer to
+    let mut syms: Vec<Rc<RefCell<Symbol>>> = [&ctx.Args.Entry].into_iter().chain(&ctx.Args.Undefined)
+        .filter_map(|name| ctx.SymbolMap.get(name).cloned())
+        .collect();
+    if ctx.IsDynamic() {
+        syms.extend(CollectExportedSymbols(ctx));
+    }
+    for sym in syms {
+        if let Some(isec) = &sym.borrow().InputSection {
+            if !isec.borrow().IsAlive {
+                isec.borrow_mut().IsAlive = true;
+                roots.push(isec.clone());
+            }
+        }
+    }
+
+    // the FDE of a function keeps what it refers to alive, like the LSDA and
+    // the personality routine. they are in the same file as the function
+    let mut fdes: BTreeMap<*mut InputSection, Vec<Rela>> = BTreeMap::new();
+    for isec in ehframe::EhFrameSections(ctx) {
+        let i = isec.borrow();
+        let eh = i.EhFrame.as_ref().unwrap();
+        for fde in &eh.Fdes {
+            if let Some(target) = &fde.Target {
+                // the first one refers to the function itself
+                fdes.entry(target.as_ptr()).or_default().extend(i.Rels[fde.Rels.start + 1..fde.Rels.end].iter()
+                    .chain(&i.Rels[eh.Cies[fde.Cie].Rels.clone()])
+                    .cloned());
+            }
+        }
+    }
+
+    // mark
+    while let Some(isec) = roots.pop() {
+        let fdeRels = fdes.get(&isec.as_ptr());
+        let isec = isec.borrow();
+        let file = isec.File.borrow();
+        for rel in isec.Rels.iter().chain(fdeRels.into_iter().flatten()) {
+            let sym = file.Symbols.get(&(rel.Sym as usize)).unwrap().borrow();
+            if let Some(target) = &sym.InputSection {
+                // a section referring to itself is already alive, and never borrowed mutably
+                if !target.borrow().IsAlive {
+                    target.borrow_mut().IsAlive = true;
+                    roots.push(target.clone());
+                }
+            }
+        }
+    }
+
+    // sweep
+    for isec in candidates {
+        let isec = isec.borrow();
+        if !isec.IsAlive && ctx.Args.PrintGcSections {
+            info!("removing unused section '{}' in file '{}'", isec.Name(), isec.File.borrow().DisplayName());
+        }
+    }
+}
+
+/// `--icf`. identical sections are folded into one, and the symbols
+/// defined in the removed ones are redirected to the one left
+pub fn IcfSections(ctx: &mut Context) {
+    let addrTaken = match ctx.Args.Icf {
+        Icf::Safe => CollectAddressTakenSections(ctx),
+        _ => BTreeSet::new()
+    };
+
+    // read-only sections without side effects
+    let mut sections: Vec<Rc<RefCell<InputSection>>> = vec![];
+    for file in &ctx.Objs {
+        for isec in file.borrow().Sections.iter().flatten() {
+            let i = isec.borrow();
+            let shdr = i.Shdr();
+            let name = i.Name();
+            let eligible = i.IsAlive && i.ShSize > 0
+                && shdr.Type == abi::SHT_PROGBITS
+                && shdr.Flags & abi::SHF_ALLOC as u64 != 0
+                && shdr.Flags & abi::SHF_WRITE as u64 == 0
+                && ![".init", ".fini", ".eh_frame"].contains(&name.as_str())
+                // __start_/__stop_ iteration sees every member
+                && !IsCIdentifier(&i.OutputSection.borrow().Name)
+                && match ctx.Args.Icf {
+                    Icf::Safe => shdr.Flags & abi::SHF_EXECINSTR as u64 != 0 && !addrTaken.contains(&isec.as_ptr()),
+                    _ => true
+                };
+            if eligible {
+                sections.push(isec.clone());
+            }
+        }
+    }
+
+    let index: BTreeMap<*mut InputSection, usize> = sections.iter().enumerate()
+        .map(|(i, isec)| (isec.as_ptr(), i)).collect();
+
+    // relocations referring to other eligible sections are compared by the
+    // classes of the targets, which are only known after refining.
+    // everything else must be exactly the same
+    let mut keys = vec![];
+    let mut edges: Vec<Vec<usize>> = vec![];
+    for isec in &sections {
+        let i = isec.borrow();
+        let file = i.File.borrow();
+        let mut rels = vec![];
+        let mut targets = vec![];
+        for (idx, rel) in i.Rels.iter().enumerate() {
+            let sym = file.Symbols.get(&(rel.Sym as usize)).unwrap();
+            // (is eligible, the target, offset in the target)
+            let target: (bool, usize, u64) = match (i.RelFragments.get(&idx), &sym.borrow().InputSection, &sym.borrow().SectionFragment) {
+                (Some((frag, offset)), _, _) => (false, frag.as_ptr() as usize, *offset),
+                (None, Some(t), _) => match index.get(&t.as_ptr()) {
+                    Some(j) => {
+                        targets.push(*j);
+                        (true, 0, sym.borrow().Value)
+                    },
+                    None => (false, t.as_ptr() as usize, sym.borrow().Value)
+                },
+                (None, None, Some(frag)) => (false, frag.as_ptr() as usize, sym.borrow().Value),
+                // absolute or undefined symbols
+                (None, None, None) => (false, sym.as_ptr() as usize, 0)
+            };
+            rels.push((rel.Offset, rel.Type, rel.Addend, target));
+        }
+        keys.push((i.OutputSection.borrow().Idx, i.Contents.GetSlice().to_vec(), i.Shdr().Flags, i.P2Align, rels));
+        edges.push(targets);
+    }
+
+    let mut classes = Classify(keys);
+    loop {
+        let keys: Vec<(usize, Vec<usize>)> = (0..sections.len())
+            .map(|i| (classes[i], edges[i].iter().map(|j| classes[*j]).collect()))
+            .collect();
+        let refined = Classify(keys);
+        // a refined class is never merged again. stop when nothing splits
+        let count = |c: &Vec<usize>| c.iter().max().map_or(0, |m| m + 1);
+        if count(&refined) == count(&classes) {
+            break;
+        }
+        classes = refined;
+    }
+
+    // the first section of each class is kept
+    let mut leaders: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut folded: BTreeMap<*mut InputSection, Rc<RefCell<InputSection>>> = BTreeMap::new();
+    let mut report: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (i, isec) in sections.iter().enumerate() {
+        match leaders.get(&classes[i]) {
+            None => { leaders.insert(classes[i], i); },
+            Some(leader) => {
+                isec.borrow_mut().IsAlive = false;
+                folded.insert(isec.as_ptr(), sections[*leader].clone());
+                report.entry(*leader).or_default().push(i);
+            }
+        }
+    }
+
+    for file in &ctx.Objs {
+        for sym in file.borrow().Symbols.values() {
+            let leader = match &sym.borrow().InputSection {
+                Some(isec) => folded.get(&isec.as_ptr()).cloned(),
+                None => None
+            };
+            if leader.is_some() {
+                sym.borrow_mut().InputSection = leader;
+            }
+        }
+    }
+
+    if ctx.Args.PrintIcfSections {
+        let display = |i: usize| {
+            let isec = sections[i].borrow();
+            format!("{}:({})", isec.File.borrow().DisplayName(), isec.Name())
+        };
+        for (leader, removed) in &report {
+            info!("selected section {}", display(*leader));
+            for i in removed {
+                info!("  removing identical section {}", display(*i));
+            }
+        }
+    }
+}
+
+/// sections referred to by anything other than jumps and calls
+fn CollectAddressTakenSections(ctx: &Context) -> BTreeSet<*mut InputSection> {
+    let mut set = BTreeSet::new();
+    for file in &ctx.Objs {
+        let obj = file.borrow();
+        for isec in obj.Sections.iter().flatten() {
+            let isec = isec.borrow();
+            // debug info and unwind tables refer to every function
+            if !isec.IsAlive || isec.Shdr().Flags & abi::SHF_ALLOC as u64 == 0 || isec.Name() == ".eh_frame" {
+                continue;
+            }
+
+            for rel in &isec.Rels {
+                let isJump = [
+                    abi::R_RISCV_NONE, abi::R_RISCV_RELAX, abi::R_RISCV_ALIGN,
+                    abi::R_RISCV_CALL, abi::R_RISCV_CALL_PLT, abi::R_RISCV_JAL, abi::R_RISCV_BRANCH,
+                    abi::R_RISCV_RVC_JUMP, abi::R_RISCV_RVC_BRANCH,
+                    // these refer to the label of the paired auipc
+                    abi::R_RISCV_PCREL_LO12_I, abi::R_RISCV_PCREL_LO12_S,
+                ].contains(&rel.Type);
+                if isJump {
+                    continue;
+                }
+                if let Some(target) = &obj.Symbols.get(&(rel.Sym as usize)).unwrap().borrow().InputSection {
+                    set.insert(target.as_ptr());
+                }
+            }
+        }
+    }
+    set
+}
+
+/// number the keys. equal keys get the same number
+fn Classify<K: Ord>(keys: Vec<K>) -> Vec<usize> {
+    let mut ids: BTreeMap<K, usize> = BTreeMap::new();
+    let mut classes = vec![];
+    for key in keys {
+        let next = ids.len();
+        classes.push(*ids.entry(key).or_insert(next));
+    }
+    classes
+}
+
 pub fn ComputeMergedSectionSizes(ctx: &mut Context) {
     for osec in &ctx.MergedSections {
         osec.borrow_mut().AssignOffsets();
@@ -0,0 +1,149 @@
+//! RISC-V linker relaxation
+//!
+//! code sequences marked by R_RISCV_RELAX may be rewritten into shorter ones
+//! once the final addresses are roughly known. the removed bytes are recorded
+//! per section, so symbol values and relocation offsets can be adjusted later.
+//! R_RISCV_ALIGN marks nop padding, and the surplus of it is deleted as well
+
+use elf::abi::*;
+use super::common::*;
+use super::inputsections::InputSection;
+
+/// what happened to a relocation during relaxation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Relaxation {
+	/// auipc + jalr -> jal
+	CallToJal,
+	/// auipc + jalr -> c.j
+	CallToCJ,
+	/// the lui is deleted
+	RemoveLui,
+	/// lo12 instruction now uses x0 as its base register
+	AbsLo12,
+	/// lo12 instruction now uses gp as its base register
+	GpLo12,
+	/// the number of nop bytes kept for R_RISCV_ALIGN
+	Align(u64),
+}
+
+impl InputSection {
+	/// decide how to relax this section. the addresses of everything must be
+	/// computed before calling this. returns (deletions, relaxations)
+	///
+	/// R_RISCV_ALIGN is always processed, as the nop padding emitted by the
+	/// assembler is only correct once the surplus is deleted. other
+	/// relaxations are done only when `relax` is set
+	///
+	/// addresses only shrink during relaxation, so a jump that is in range now
+	/// is expected to stay in range after the final layout. the relocation
+	/// code checks it again anyway
+	///
+	/// the distance to gp is another matter: sections in between shrink, and
+	/// section alignment may keep gp where it is. so gp relaxation leaves
+	/// `margin`, the most bytes relaxation may delete, for everything outside
+	/// .sdata
+	pub fn Relax(&self, gp: u64, margin: u64, rvc: bool, relax: bool) -> (Vec<(u64, u64)>, BTreeMap<usize, Relaxation>) {
+		let mut deltas = vec![];
+		let mut relaxations = BTreeMap::new();
+
+		let file = self.File.borrow();
+		let contents = self.Contents.GetSlice();
+		let base = self.GetAddr();
+		let isRelaxable = |idx: usize| {
+			self.Rels.get(idx + 1).is_some_and(|r| r.Type == R_RISCV_RELAX && r.Offset == self.Rels[idx].Offset)
+		};
+		let fitsI12 = |val: i64| (-2048..2048).contains(&val);
+		let fitsGp = |SA: u64, margin: i64| gp != 0 && (-2048 + margin..2048 - margin).contains(&(SA.wrapping_sub(gp) as i64));
+
+		for (idx, rel) in self.Rels.iter().enumerate() {
+			if rel.Type == R_RISCV_ALIGN {
+				// the section itself is aligned at least as strictly as this,
+				// so it's enough to look at the offset inside the section
+				let P = base + rel.Offset - deltas.last().map_or(0, |d: &(u64, u64)| d.1);
+				let padding = rel.Addend as u64;
+				let desired = AlignTo(P as usize, (padding + 1).next_power_of_two() as usize) as u64;
+				let keep = desired - P;
+				if keep < padding {
+					remove(&mut deltas, rel.Offset + keep, padding - keep);
+				}
+				relaxations.insert(idx, Relaxation::Align(keep));
+				continue;
+			}
+
+			if !relax || !isRelaxable(idx) {
+				continue;
+			}
+
+			let sym = file.Symbols.get(&(rel.Sym as usize)).unwrap().borrow();
+			// undefined symbols resolve to 0, but may be resolved at runtime later
+			if sym.File.is_none() {
+				continue;
+			}
+			let SA = self.GetSA(idx, rel, &sym);
+			// gp is 0x800 past the start of .sdata, and moves along with it
+			let osecAddr = match (&sym.InputSection, &sym.SectionFragment) {
+				(Some(isec), _) => Some(isec.borrow().OutputSection.borrow().Shdr.Addr),
+				(None, Some(frag)) => Some(frag.borrow().OutputSection.borrow().Shdr.Addr),
+				_ => None
+			};
+			let margin = if osecAddr.is_some_and(|addr| addr + 0x800 == gp) { 0 } else { margin as i64 };
+
+			match rel.Type {
+				// it's going to be rewritten as a whole
+				R_RISCV_CALL | R_RISCV_CALL_PLT if self.GetTlsGdReg(rel, &sym).is_some() => {},
+				R_RISCV_CALL | R_RISCV_CALL_PLT => {
+					let dist = SA.wrapping_sub(base + rel.Offset) as i64;
+					let jalr = Read::<u32>(&contents[rel.Offset as usize + 4..]);
+					let rd = (jalr >> 7) & 0b11111;
+
+					if rvc && rd == 0 && (-(1 << 11)..(1 << 11)).contains(&dist) {
+						remove(&mut deltas, rel.Offset + 2, 6);
+						relaxations.insert(idx, Relaxation::CallToCJ);
+					}
+					else if (-(1 << 20)..(1 << 20)).contains(&dist) {
+						remove(&mut deltas, rel.Offset + 4, 4);
+						relaxations.insert(idx, Relaxation::CallToJal);
+					}
+				},
+				R_RISCV_HI20 if fitsI12(SA as i64) || fitsGp(SA, margin) => {
+					remove(&mut deltas, rel.Offset, 4);
+					relaxations.insert(idx, Relaxation::RemoveLui);
+				},
+				R_RISCV_LO12_I | R_RISCV_LO12_S if fitsI12(SA as i64) => {
+					relaxations.insert(idx, Relaxation::AbsLo12);
+				},
+				R_RISCV_LO12_I | R_RISCV_LO12_S if fitsGp(SA, margin) => {
+					relaxations.insert(idx, Relaxation::GpLo12);
+				},
+				_ => {}
+			}
+		}
+
+		(deltas, relaxations)
+	}
+
+	/// an upper bound of the bytes `Relax` deletes from this section
+	pub fn MaxDeletion(&self) -> u64 {
+		self.Rels.iter().map(|rel| match rel.Type {
+			R_RISCV_ALIGN => rel.Addend as u64,
+			R_RISCV_CALL | R_RISCV_CALL_PLT => 6,
+			R_RISCV_HI20 => 4,
+			_ => 0
+		}).sum()
+	}
+
+	/// the number of bytes deleted before `offset`
+	pub fn GetDelta(&self, offset: u64) -> u64 {
+		let pos = self.Deltas.partition_point(|(off, _)| *off < offset);
+		match pos {
+			0 => 0,
+			_ => self.Deltas[pos - 1].1
+		}
+	}
+}
+
+/// delete `size` bytes at `offset`. the deltas hold accumulated sizes
+fn remove(deltas: &mut Vec<(u64, u64)>, offset: u64, size: u64) {
+	let delta = deltas.last().map_or(0, |d| d.1);
+	deltas.push((offset, delta + size));
+}
@@ -0,0 +1,391 @@
+//! apply RISC-V relocations to the contents of input sections
+//!
+//! S: the address of the symbol
+//! A: the addend
+//! P: the address of the place being relocated
+
+use elf::abi::*;
+use super::common::*;
+use super::elf::Rela;
+use super::inputsections::InputSection;
+use super::relax::Relaxation;
+use super::symbol::Symbol;
+
+impl InputSection {
+	/// patch `buf`, which already holds a copy of this section's contents
+	pub fn ApplyRelocs(&self, ctx: &Context, buf: &mut [u8]) {
+		if self.Rels.is_empty() {
+			return;
+		}
+
+		let file = self.File.borrow();
+		for (idx, rel) in self.Rels.iter().enumerate() {
+			if rel.Type == R_RISCV_NONE || rel.Type == R_RISCV_RELAX {
+				continue;
+			}
+
+			let sym = file.Symbols.get(&(rel.Sym as usize)).unwrap().borrow();
+			// relaxation may have moved this relocation
+			let offset = rel.Offset - self.GetDelta(rel.Offset);
+			let loc = &mut buf[offset as usize..];
+			let SA = self.GetSA(idx, rel, &sym);
+			let P = self.GetAddr() + offset;
+
+			if let Some(r) = self.Relaxations.get(&idx) {
+				self.WriteRelaxed(ctx, rel, loc, *r, SA, P);
+				continue;
+			}
+			self.ApplyReloc(ctx, rel, &sym, loc, SA, P);
+		}
+	}
+
+	/// patch `loc` for a relocation, whose value is computed from `SA` and `P`
+	pub fn ApplyReloc(&self, ctx: &Context, rel: &Rela, sym: &Symbol, loc: &mut [u8], SA: u64, P: u64) {
+		match rel.Type {
+			R_RISCV_32 => Write::<u32>(loc, &(SA as u32)),
+			R_RISCV_64 => Write::<u64>(loc, &SA),
+			R_RISCV_BRANCH => {
+				let val = SA.wrapping_sub(P);
+				checkRange(rel, val as i64, -(1 << 12), 1 << 12);
+				writeBtype(loc, val as u32);
+			},
+			R_RISCV_JAL => {
+				let val = SA.wrapping_sub(P);
+				checkRange(rel, val as i64, -(1 << 20), 1 << 20);
+				writeJtype(loc, val as u32);
+			},
+			R_RISCV_CALL | R_RISCV_CALL_PLT => match self.GetTlsGdReg(rel, sym) {
+				// the address has been computed relative to tp, or
+				// loaded from the GOT. add tp to it instead of the call
+				Some(reg) => {
+					Write::<u32>(loc, &(ADD_A0_TP | reg << 15));
+					Write::<u32>(&mut loc[4..], &NOP);
+				},
+				None => {
+					// auipc + jalr
+					let val = SA.wrapping_sub(P) as u32;
+					writeUtype(loc, val);
+					writeItype(&mut loc[4..], val);
+				}
+			},
+			R_RISCV_PCREL_HI20 | R_RISCV_GOT_HI20 | R_RISCV_TLS_GOT_HI20 => writeUtype(loc, self.GetHi20Value(ctx, rel, sym, SA, P) as u32),
+			R_RISCV_TLS_GD_HI20 => {
+				// auipc -> lui, when the offset from tp is known
+				if !sym.IsImported() {
+					let insn = (Read::<u32>(loc) & 0b11111_0000000) | 0b0110111;
+					Write::<u32>(loc, &insn);
+				}
+				writeUtype(loc, self.GetHi20Value(ctx, rel, sym, SA, P) as u32);
+			},
+			R_RISCV_TPREL_HI20 => writeUtype(loc, SA.wrapping_sub(ctx.TpAddr) as u32),
+			R_RISCV_TPREL_LO12_I => writeItype(loc, SA.wrapping_sub(ctx.TpAddr) as u32),
+			R_RISCV_TPREL_LO12_S => writeStype(loc, SA.wrapping_sub(ctx.TpAddr) as u32),
+			// only marks the `add rd, rd, tp`
+			R_RISCV_TPREL_ADD => {},
+			R_RISCV_HI20 => writeUtype(loc, SA as u32),
+			R_RISCV_LO12_I => writeItype(loc, SA as u32),
+			R_RISCV_LO12_S => writeStype(loc, SA as u32),
+			R_RISCV_PCREL_LO12_I | R_RISCV_PCREL_LO12_S => {
+				// the symbol points to the auipc instruction, whose
+				// relocation holds the real target
+				let (val, hiType, imported) = self.GetPcrelHi20Value(ctx, sym);
+				// `addi` of a general dynamic TLS access loads the offset
+				// from the GOT instead, for imported variables. it becomes
+				// `ld` by replacing funct3 and the opcode
+				if hiType == R_RISCV_TLS_GD_HI20 && imported {
+					let insn = (Read::<u32>(loc) & !0x707f) | 0x3003;
+					Write::<u32>(loc, &insn);
+				}
+				if rel.Type == R_RISCV_PCREL_LO12_I {
+					writeItype(loc, val);
+				}
+				else {
+					writeStype(loc, val);
+				}
+			},
+			R_RISCV_RVC_BRANCH => {
+				let val = SA.wrapping_sub(P);
+				checkRange(rel, val as i64, -(1 << 8), 1 << 8);
+				writeCBtype(loc, val as u16);
+			},
+			R_RISCV_RVC_JUMP => {
+				let val = SA.wrapping_sub(P);
+				checkRange(rel, val as i64, -(1 << 11), 1 << 11);
+				writeCJtype(loc, val as u16);
+			},
+			R_RISCV_ADD8 => loc[0] = loc[0].wrapping_add(SA as u8),
+			R_RISCV_ADD16 => {
+				let val = Read::<u16>(loc).wrapping_add(SA as u16);
+				Write::<u16>(loc, &val);
+			},
+			R_RISCV_ADD32 => {
+				let val = Read::<u32>(loc).wrapping_add(SA as u32);
+				Write::<u32>(loc, &val);
+			},
+			R_RISCV_ADD64 => {
+				let val = Read::<u64>(loc).wrapping_add(SA);
+				Write::<u64>(loc, &val);
+			},
+			R_RISCV_SUB6 => loc[0] = (loc[0] & 0b1100_0000) | (loc[0].wrapping_sub(SA as u8) & 0b0011_1111),
+			R_RISCV_SUB8 => loc[0] = loc[0].wrapping_sub(SA as u8),
+			R_RISCV_SUB16 => {
+				let val = Read::<u16>(loc).wrapping_sub(SA as u16);
+				Write::<u16>(loc, &val);
+			},
+			R_RISCV_SUB32 => {
+				let val = Read::<u32>(loc).wrapping_sub(SA as u32);
+				Write::<u32>(loc, &val);
+			},
+			R_RISCV_SUB64 => {
+				let val = Read::<u64>(loc).wrapping_sub(SA);
+				Write::<u64>(loc, &val);
+			},
+			R_RISCV_SET6 => loc[0] = (loc[0] & 0b1100_0000) | (SA as u8 & 0b0011_1111),
+			R_RISCV_SET8 => loc[0] = SA as u8,
+			R_RISCV_SET16 => Write::<u16>(loc, &(SA as u16)),
+			R_RISCV_SET32 => Write::<u32>(loc, &(SA as u32)),
+			R_RISCV_32_PCREL => Write::<u32>(loc, &(SA.wrapping_sub(P) as u32)),
+			R_RISCV_ALIGN => {},
+			_ => {
+				error!("{}: unsupported relocation type {} at {:#x}", self.File.borrow().Name(), rel.Type, rel.Offset);
+			}
+		}
+	}
+
+	/// rewrite an instruction sequence shrunk by relaxation
+	fn WriteRelaxed(&self, ctx: &Context, rel: &Rela, loc: &mut [u8], r: Relaxation, SA: u64, P: u64) {
+		match r {
+			Relaxation::CallToJal => {
+				// keep the rd of jalr, which has been deleted from `loc`
+				let jalr = Read::<u32>(&self.Contents.GetSlice()[rel.Offset as usize + 4..]);
+				let rd = jalr & (0b11111 << 7);
+				Write::<u32>(loc, &(0b1101111 | rd));
+				let val = SA.wrapping_sub(P);
+				checkRange(rel, val as i64, -(1 << 20), 1 << 20);
+				writeJtype(loc, val as u32);
+			},
+			Relaxation::CallToCJ => {
+				// c.j
+				Write::<u16>(loc, &0xa001);
+				let val = SA.wrapping_sub(P);
+				checkRange(rel, val as i64, -(1 << 11), 1 << 11);
+				writeCJtype(loc, val as u16);
+			},
+			Relaxation::RemoveLui => {},
+			Relaxation::AbsLo12 | Relaxation::GpLo12 => {
+				let (rs1, val) = match r {
+					Relaxation::GpLo12 => (GP, SA.wrapping_sub(ctx.GpAddr)),
+					_ => (0, SA)
+				};
+				checkRange(rel, val as i64, -(1 << 11), 1 << 11);
+				let insn = (Read::<u32>(loc) & !(0b11111 << 15)) | rs1 << 15;
+				Write::<u32>(loc, &insn);
+				if rel.Type == R_RISCV_LO12_I {
+					writeItype(loc, val as u32);
+				}
+				else {
+					writeStype(loc, val as u32);
+				}
+			},
+			Relaxation::Align(keep) => {
+				// the padding left may end in the middle of a 4-byte nop
+				let keep = keep as usize;
+				for pos in (0..keep / 4 * 4).step_by(4) {
+					Write::<u32>(&mut loc[pos..], &NOP);
+				}
+				if !keep.is_multiple_of(4) {
+					Write::<u16>(&mut loc[keep - 2..], &C_NOP);
+				}
+			},
+		}
+	}
+
+	/// `S + A` of the `idx`th relocation
+	pub fn GetSA(&self, idx: usize, rel: &Rela, sym: &Symbol) -> u64 {
+		match self.RelFragments.get(&idx) {
+			Some((frag, offset)) => frag.borrow().GetAddr() + offset,
+			// undefined weak symbols(or ignored undefined ones) resolve to 0
+			None => sym.GetAddr().wrapping_add(rel.Addend as u64)
+		}
+	}
+
+	/// the value of a hi20 relocation paired with PCREL_LO12, `S + A - P`
+	/// usually. S is the symbol's GOT slot for R_RISCV_GOT_HI20 and TLS_GOT_HI20.
+	/// TLS_GD_HI20 refers to the GOT slot too for imported variables, otherwise
+	/// it's the offset from tp, as the instructions become local exec ones
+	fn GetHi20Value(&self, ctx: &Context, rel: &Rela, sym: &Symbol, SA: u64, P: u64) -> u64 {
+		match rel.Type {
+			R_RISCV_GOT_HI20 | R_RISCV_TLS_GOT_HI20 => sym.GetGotAddr(ctx).wrapping_add(rel.Addend as u64).wrapping_sub(P),
+			R_RISCV_TLS_GD_HI20 if sym.IsImported() => sym.GetGotAddr(ctx).wrapping_sub(P),
+			R_RISCV_TLS_GD_HI20 => SA.wrapping_sub(ctx.TpAddr),
+			_ => SA.wrapping_sub(P)
+		}
+	}
+
+	/// find the hi20 relocation paired with a PCREL_LO12 relocation and
+	/// compute its value. its type and whether the symbol is imported are
+	/// returned as well, as they may change the instruction
+	fn GetPcrelHi20Value(&self, ctx: &Context, sym: &Symbol) -> (u32, u32, bool) {
+		let file = self.File.borrow();
+		if !sym.InputSection.as_ref().is_some_and(|isec| std::ptr::eq(isec.as_ptr(), self)) {
+			error!("{}: PCREL_LO12 label {} is not in the same section", file.Name(), sym.Name);
+		}
+		for (idx, rel) in self.Rels.iter().enumerate() {
+			// symbol values have been adjusted by relaxation already
+			let offset = rel.Offset - self.GetDelta(rel.Offset);
+			let isHi20 = [R_RISCV_PCREL_HI20, R_RISCV_GOT_HI20, R_RISCV_TLS_GOT_HI20, R_RISCV_TLS_GD_HI20].contains(&rel.Type);
+			if offset != sym.Value || !isHi20 {
+				continue;
+			}
+
+			let hisym = file.Symbols.get(&(rel.Sym as usize)).unwrap().borrow();
+			let SA = self.GetSA(idx, rel, &hisym);
+			let val = self.GetHi20Value(ctx, rel, &hisym, SA, self.GetAddr() + offset);
+			return (val as u32, rel.Type, hisym.IsImported());
+		}
+
+		error!("{}: R_RISCV_PCREL_LO12 without a matching HI20 at {:#x}", file.Name(), sym.Value);
+		(0, 0, false)
+	}
+
+	/// the register holding the address computed by a general dynamic TLS
+	/// access, if this is the call to `__tls_get_addr` following it. the output
+	/// is always an executable, so the call is rewritten. the call is paired
+	/// with the closest PCREL_LO12_I before it, whose label is the auipc of
+	/// R_RISCV_TLS_GD_HI20, and the register is the one the addi writes to
+	pub fn GetTlsGdReg(&self, rel: &Rela, sym: &Symbol) -> Option<u32> {
+		if (rel.Type != R_RISCV_CALL && rel.Type != R_RISCV_CALL_PLT) || sym.Name != "__tls_get_addr" {
+			return None;
+		}
+
+		// another call in between takes the argument as its own
+		let lo = self.Rels.iter()
+			.filter(|r| r.Offset < rel.Offset && [R_RISCV_PCREL_LO12_I, R_RISCV_CALL, R_RISCV_CALL_PLT].contains(&r.Type))
+			.max_by_key(|r| r.Offset)?;
+		if lo.Type != R_RISCV_PCREL_LO12_I {
+			return None;
+		}
+
+		let file = self.File.borrow();
+		let label = file.Symbols.get(&(lo.Sym as usize)).unwrap().borrow();
+		let isec = label.InputSection.as_ref()?;
+		if !std::ptr::eq(isec.as_ptr(), self) {
+			return None;
+		}
+		// symbol values are adjusted once relaxation is done
+		let hi = self.Rels.iter()
+			.find(|r| r.Type == R_RISCV_TLS_GD_HI20 && r.Offset - self.GetDelta(r.Offset) == label.Value)?;
+
+		let contents = self.Contents.GetSlice();
+		let auipc = Read::<u32>(&contents[hi.Offset as usize..]);
+		let addi = Read::<u32>(&contents[lo.Offset as usize..]);
+		if bits(addi, 19, 15) != bits(auipc, 11, 7) {
+			return None;
+		}
+		Some(bits(addi, 11, 7))
+	}
+}
+
+/// the gp register
+const GP: u32 = 3;
+/// addi zero, zero, 0
+const NOP: u32 = 0x00000013;
+const C_NOP: u16 = 0x0001;
+/// add a0, rs1, tp
+const ADD_A0_TP: u32 = 0x00400533;
+
+fn checkRange(rel: &Rela, val: i64, lo: i64, hi: i64) {
+	if val < lo || val >= hi {
+		error!("relocation {} at {:#x} out of range: {} is not in [{}, {})", rel.Type, rel.Offset, val, lo, hi);
+	}
+}
+
+fn bit(val: u32, pos: u32) -> u32 {
+	(val >> pos) & 1
+}
+
+/// val[hi:lo]
+fn bits(val: u32, hi: u32, lo: u32) -> u32 {
+	(val >> lo) & ((1 << (hi - lo + 1)) - 1)
+}
+
+fn itype(val: u32) -> u32 {
+	val << 20
+}
+
+fn stype(val: u32) -> u32 {
+	bits(val, 11, 5) << 25 | bits(val, 4, 0) << 7
+}
+
+fn btype(val: u32) -> u32 {
+	bit(val, 12) << 31 | bits(val, 10, 5) << 25 | bits(val, 4, 1) << 8 | bit(val, 11) << 7
+}
+
+/// the low 12 bits are sign-extended by the paired instruction,
+/// so round the high part up when bit 11 is set
+fn utype(val: u32) -> u32 {
+	val.wrapping_add(0x800) & 0xffff_f000
+}
+
+fn jtype(val: u32) -> u32 {
+	bit(val, 20) << 31 | bits(val, 10, 1) << 21 | bit(val, 11) << 20 | bits(val, 19, 12) << 12
+}
+
+fn cbtype(val: u16) -> u16 {
+	let val = val as u32;
+	(bit(val, 8) << 12 | bit(val, 4) << 11 | bit(val, 3) << 10 |
+		bit(val, 7) << 6 | bit(val, 6) << 5 | bit(val, 2) << 4 |
+		bit(val, 1) << 3 | bit(val, 5) << 2) as u16
+}
+
+fn cjtype(val: u16) -> u16 {
+	let val = val as u32;
+	(bit(val, 11) << 12 | bit(val, 4) << 11 | bit(val, 9) << 10 |
+		bit(val, 8) << 9 | bit(val, 10) << 8 | bit(val, 6) << 7 |
+		bit(val, 7) << 6 | bit(val, 3) << 5 | bit(val, 2) << 4 |
+		bit(val, 1) << 3 | bit(val, 5) << 2) as u16
+}
+
+// each writer keeps the opcode, funct3 and registers, and replaces the immediate
+
+pub fn writeItype(loc: &mut [u8], val: u32) {
+	let mask = 0x000fffff;
+	let insn = (Read::<u32>(loc) & mask) | itype(val);
+	Write::<u32>(loc, &insn);
+}
+
+fn writeStype(loc: &mut [u8], val: u32) {
+	let mask = 0x01fff07f;
+	let insn = (Read::<u32>(loc) & mask) | stype(val);
+	Write::<u32>(loc, &insn);
+}
+
+fn writeBtype(loc: &mut [u8], val: u32) {
+	let mask = 0x01fff07f;
+	let insn = (Read::<u32>(loc) & mask) | btype(val);
+	Write::<u32>(loc, &insn);
+}
+
+pub fn writeUtype(loc: &mut [u8], val: u32) {
+	let mask = 0x00000fff;
+	let insn = (Read::<u32>(loc) & mask) | utype(val);
+	Write::<u32>(loc, &insn);
+}
+
+fn writeJtype(loc: &mut [u8], val: u32) {
+	let mask = 0x00000fff;
+	let insn = (Read::<u32>(loc) & mask) | jtype(val);
+	Write::<u32>(loc, &insn);
+}
+
+fn writeCBtype(loc: &mut [u8], val: u16) {
+	let mask = 0xe383;
+	let insn = (Read::<u16>(loc) & mask) | cbtype(val);
+	Write::<u16>(loc, &insn);
+}
+
+fn writeCJtype(loc: &mut [u8], val: u16) {
+	let mask = 0xe003;
+	let insn = (Read::<u16>(loc) & mask) | cjtype(val);
+	Write::<u16>(loc, &insn);
+}
@@ -0,0 +1,1398 @@
+//! GNU ld compatible linker scripts(`-T`). a subset is supported:
+//! ENTRY, MEMORY, PHDRS, SECTIONS with input section descriptions, KEEP,
+//! /DISCARD/, AT, ALIGN, memory regions, symbol assignments,
+//! PROVIDE/PROVIDE_HIDDEN/HIDDEN and ASSERT
+//!
+//! the headers are not loaded when a linker script is in use
+
+use super::common::*;
+use super::file::File;
+use super::elf::PAGESIZE;
+use super::inputsections::InputSection;
+use super::output::{ptr2ref_dyn, OutputSection};
+use super::passes::isTbss;
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+	Num(u64),
+	/// the location counter `.`
+	Dot,
+	Symbol(String),
+	Unary(String, Box<Expr>),
+	Binary(String, Box<Expr>, Box<Expr>),
+	Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+	/// builtin functions like ALIGN, ADDR and ORIGIN
+	Call(String, Vec<Expr>),
+}
+
+/// `sym = expr`. compound assignments like `+=` are expanded when parsing
+#[derive(Debug, Clone)]
+pub struct Assignment {
+	pub Name:		String,
+	pub Expr:		Expr,
+	/// PROVIDE and PROVIDE_HIDDEN. only defined when referenced but not defined
+	pub Provide:	bool,
+	pub Hidden:		bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKind {
+	None,
+	Name,
+	Alignment,
+	InitPriority,
+}
+
+/// `*(.text .text.*)` and friends, in an output section description
+#[derive(Debug, Clone)]
+pub struct InputSectionDesc {
+	/// the output section this belongs to
+	pub Output:			String,
+	/// `(NOLOAD)` of the output section
+	pub NoLoad:			bool,
+	pub FilePattern:	String,
+	pub ExcludeFiles:	Vec<String>,
+	pub SectionPatterns:	Vec<String>,
+	pub Keep:			bool,
+	pub Sort:			SortKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum OutputSectionCommand {
+	/// index into `LinkerScript::Inputs`
+	Input(usize),
+	Assign(Assignment),
+	Assert(Expr, String),
+}
+
+#[derive(Debug, Clone)]
+pub struct OutputSectionDesc {
+	pub Name:		String,
+	pub Addr:		Option<Expr>,
+	/// AT(lma)
+	pub Lma:		Option<Expr>,
+	pub Align:		Option<Expr>,
+	pub NoLoad:		bool,
+	/// `> region`
+	pub Region:		Option<String>,
+	/// `AT> region`
+	pub LmaRegion:	Option<String>,
+	/// `:phdr`
+	pub Phdrs:		Vec<String>,
+	pub Commands:	Vec<OutputSectionCommand>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+	Assign(Assignment),
+	Assert(Expr, String),
+	Output(OutputSectionDesc),
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryRegion {
+	pub Name:	String,
+	pub Origin:	Expr,
+	pub Length:	Expr,
+}
+
+/// an entry of PHDRS
+#[derive(Debug, Clone)]
+pub struct PhdrDesc {
+	pub Name:	String,
+	pub Type:	u32,
+	pub Flags:	Option<u32>,
+}
+
+#[derive(Debug, Default)]
+pub struct LinkerScript {
+	pub Path:		String,
+	pub Entry:		Option<String>,
+	pub SearchDirs:	Vec<String>,
+	pub Memory:		Vec<MemoryRegion>,
+	pub Phdrs:		Vec<PhdrDesc>,
+	/// top-level commands and the ones in SECTIONS, in the order of appearance
+	pub Commands:	Vec<Command>,
+	pub Inputs:		Vec<InputSectionDesc>,
+	/// files named by INPUT and GROUP, and whether they are in AS_NEEDED
+	pub InputFiles:	Vec<(String, bool)>,
+
+	// results of the layout
+	/// values of the symbols assigned by the script
+	pub Values:		BTreeMap<String, u64>,
+	/// output section name -> (load address - virtual address)
+	pub LoadOffsets:	BTreeMap<String, u64>,
+	/// output section name -> the PHDRS entries it goes to
+	pub Segments:	BTreeMap<String, Vec<String>>,
+	/// failed ASSERTs and overflowed memory regions. reported after the final layout
+	pub Errors:		Vec<String>,
+}
+
+impl LinkerScript {
+	pub fn Parse(path: &str, src: &str) -> LinkerScript {
+		let mut script = LinkerScript { Path: path.into(), ..Default::default() };
+		let mut p = Parser { src: src.as_bytes(), pos: 0, path };
+
+		while !p.atEnd() {
+			let tok = p.peek();
+			match tok.as_str() {
+				";" => { p.token(); },
+				"ENTRY" => {
+					p.token();
+					p.expect("(");
+					script.Entry = Some(p.word());
+					p.expect(")");
+				},
+				// archives are searched repeatedly anyway, so GROUP is just INPUT
+				"INPUT" | "GROUP" => {
+					p.token();
+					script.ParseInputFiles(&mut p, false);
+				},
+				"SEARCH_DIR" => {
+					p.token();
+					p.expect("(");
+					script.SearchDirs.push(p.word());
+					p.expect(")");
+				},
+				// we know what the output is
+				"OUTPUT_FORMAT" | "OUTPUT_ARCH" | "TARGET" => {
+					p.token();
+					p.skipParens();
+				},
+				"MEMORY" => {
+					p.token();
+					script.ParseMemory(&mut p);
+				},
+				"PHDRS" => {
+					p.token();
+					script.ParsePhdrs(&mut p);
+				},
+				"SECTIONS" => {
+					p.token();
+					script.ParseSections(&mut p);
+				},
+				"ASSERT" => {
+					let (e, msg) = p.assert();
+					script.Commands.push(Command::Assert(e, msg));
+				},
+				_ => {
+					let a = p.assignment();
+					p.expect(";");
+					script.Commands.push(Command::Assign(a));
+				}
+			}
+		}
+		script
+	}
+
+	fn ParseInputFiles(&mut self, p: &mut Parser, asNeeded: bool) {
+		p.expect("(");
+		while !p.consume(")") {
+			if p.consume(",") {
+				continue;
+			}
+			if p.peekWord() == "AS_NEEDED" {
+				p.word();
+				self.ParseInputFiles(p, true);
+				continue;
+			}
+			let name = p.word();
+			if name.is_empty() {
+				let tok = p.peek();
+				error!("{}: expected a file name, but got '{}'", p.path, tok);
+			}
+			self.InputFiles.push((name, asNeeded));
+		}
+	}
+
+	fn ParseMemory(&mut self, p: &mut Parser) {
+		p.expect("{");
+		while !p.consume("}") {
+			let name = p.word();
+			// attributes are only used to place orphans by GNU ld
+			if p.peek() == "(" {
+				p.skipParens();
+			}
+			p.expect(":");
+			p.expectOneOf(&["ORIGIN", "org", "o"]);
+			p.expect("=");
+			let origin = p.expr();
+			p.consume(",");
+			p.expectOneOf(&["LENGTH", "len", "l"]);
+			p.expect("=");
+			let length = p.expr();
+			self.Memory.push(MemoryRegion { Name: name, Origin: origin, Length: length });
+		}
+	}
+
+	fn ParsePhdrs(&mut self, p: &mut Parser) {
+		p.expect("{");
+		while !p.consume("}") {
+			let name = p.word();
+			let ty = p.token();
+			let ty = match ty.as_str() {
+				"PT_NULL" => abi::PT_NULL,
+				"PT_LOAD" => abi::PT_LOAD,
+				"PT_DYNAMIC" => abi::PT_DYNAMIC,
+				"PT_INTERP" => abi::PT_INTERP,
+				"PT_NOTE" => abi::PT_NOTE,
+				"PT_SHLIB" => abi::PT_SHLIB,
+				"PT_PHDR" => abi::PT_PHDR,
+				"PT_TLS" => abi::PT_TLS,
+				"PT_GNU_EH_FRAME" => abi::PT_GNU_EH_FRAME,
+				"PT_GNU_STACK" => abi::PT_GNU_STACK,
+				"PT_GNU_RELRO" => abi::PT_GNU_RELRO,
+				other => match ParseNumber(other) {
+					Some(n) => n as u32,
+					None => {
+						error!("{}: unknown program header type {}", p.path, other);
+					}
+				}
+			};
+
+			let mut flags = None;
+			loop {
+				match p.peek().as_str() {
+					// the headers are never loaded
+					"FILEHDR" | "PHDRS" => { p.token(); },
+					"AT" => {
+						error!("{}: AT in PHDRS is not supported", p.path);
+					},
+					"FLAGS" => {
+						p.token();
+						p.expect("(");
+						flags = Some(self.Eval(None, &p.expr(), 0) as u32);
+						p.expect(")");
+					},
+					_ => break
+				}
+			}
+			p.expect(";");
+			self.Phdrs.push(PhdrDesc { Name: name, Type: ty, Flags: flags });
+		}
+	}
+
+	fn ParseSections(&mut self, p: &mut Parser) {
+		p.expect("{");
+		while !p.consume("}") {
+			match p.peek().as_str() {
+				";" => { p.token(); },
+				"ENTRY" => {
+					p.token();
+					p.expect("(");
+					self.Entry = Some(p.word());
+					p.expect(")");
+				},
+				"ASSERT" => {
+					let (e, msg) = p.assert();
+					self.Commands.push(Command::Assert(e, msg));
+				},
+				_ if p.isAssignment() => {
+					let a = p.assignment();
+					p.expect(";");
+					self.Commands.push(Command::Assign(a));
+				},
+				_ => {
+					let desc = self.ParseOutputSection(p);
+					self.Commands.push(Command::Output(desc));
+				}
+			}
+		}
+	}
+
+	/// name [addr] [(NOLOAD)] : [AT(lma)] [ALIGN(align)] { ... } [>region] [AT>region] [:phdr...] [=fill]
+	fn ParseOutputSection(&mut self, p: &mut Parser) -> OutputSectionDesc {
+		let mut desc = OutputSectionDesc {
+			Name: p.word(),
+			Addr: None, Lma: None, Align: None, NoLoad: false,
+			Region: None, LmaRegion: None, Phdrs: vec![], Commands: vec![],
+		};
+
+		if p.peek() != ":" && !p.isSectionType() {
+			desc.Addr = Some(p.expr());
+		}
+		if p.isSectionType() {
+			p.expect("(");
+			desc.NoLoad = p.word() == "NOLOAD";
+			p.expect(")");
+		}
+		p.expect(":");
+
+		loop {
+			match p.peek().as_str() {
+				"AT" => {
+					p.token();
+					p.expect("(");
+					desc.Lma = Some(p.expr());
+					p.expect(")");
+				},
+				"ALIGN" => {
+					p.token();
+					p.expect("(");
+					desc.Align = Some(p.expr());
+					p.expect(")");
+				},
+				"SUBALIGN" => {
+					error!("{}: SUBALIGN is not supported", p.path);
+				},
+				_ => break
+			}
+		}
+
+		p.expect("{");
+		while !p.consume("}") {
+			match p.peekWord().as_str() {
+				"" if p.peek() == ";" => { p.token(); },
+				"ASSERT" => {
+					let (e, msg) = p.assert();
+					desc.Commands.push(OutputSectionCommand::Assert(e, msg));
+				},
+				"KEEP" => {
+					p.word();
+					p.expect("(");
+					let idx = self.ParseInputSection(p, &desc, true);
+					p.expect(")");
+					desc.Commands.push(OutputSectionCommand::Input(idx));
+				},
+				"FILL" => {
+					p.word();
+					p.skipParens();
+				},
+				"CREATE_OBJECT_SYMBOLS" | "CONSTRUCTORS" => { p.word(); },
+				"BYTE" | "SHORT" | "LONG" | "QUAD" | "SQUAD" => {
+					error!("{}: data commands are not supported", p.path);
+				},
+				_ if p.isAssignment() => {
+					let a = p.assignment();
+					p.consume(";");
+					desc.Commands.push(OutputSectionCommand::Assign(a));
+				},
+				_ => {
+					let idx = self.ParseInputSection(p, &desc, false);
+					desc.Commands.push(OutputSectionCommand::Input(idx));
+				}
+			}
+		}
+
+		loop {
+			if p.consume(">") {
+				desc.Region = Some(p.word());
+			}
+			else if p.peek() == "AT" {
+				p.token();
+				p.expect(">");
+				desc.LmaRegion = Some(p.word());
+			}
+			else if p.consume(":") {
+				desc.Phdrs.push(p.word());
+			}
+			else if p.consume("=") {
+				p.expr();
+			}
+			else {
+				break;
+			}
+		}
+		p.consume(",");
+		desc
+	}
+
+	/// file-pattern(section-pattern...). returns the index into `Inputs`
+	fn ParseInputSection(&mut self, p: &mut Parser, desc: &OutputSectionDesc, keep: bool) -> usize {
+		let mut input = InputSectionDesc {
+			Output: desc.Name.clone(),
+			NoLoad: desc.NoLoad,
+			FilePattern: String::new(),
+			ExcludeFiles: vec![],
+			SectionPatterns: vec![],
+			Keep: keep,
+			Sort: SortKind::None,
+		};
+
+		let mut file = p.word();
+		if file == "EXCLUDE_FILE" {
+			input.ExcludeFiles = p.patternList();
+			file = p.word();
+		}
+		input.FilePattern = file;
+
+		if !p.consume("(") {
+			// all the sections of the file
+			input.SectionPatterns.push("*".into());
+		}
+		else {
+			while !p.consume(")") {
+				let w = p.word();
+				match w.as_str() {
+					"SORT" | "SORT_BY_NAME" | "SORT_BY_ALIGNMENT" | "SORT_BY_INIT_PRIORITY" | "SORT_NONE" => {
+						input.Sort = match w.as_str() {
+							"SORT_BY_ALIGNMENT" => SortKind::Alignment,
+							"SORT_BY_INIT_PRIORITY" => SortKind::InitPriority,
+							"SORT_NONE" => SortKind::None,
+							_ => SortKind::Name
+						};
+						// nested ones like SORT_BY_NAME(SORT_BY_ALIGNMENT(...)) just use the outer one
+						for pat in p.patternList() {
+							if !pat.starts_with("SORT") {
+								input.SectionPatterns.push(pat);
+							}
+						}
+					},
+					"EXCLUDE_FILE" => input.ExcludeFiles.extend(p.patternList()),
+					"" => {
+						let tok = p.peek();
+						error!("{}: unexpected '{}' in input section description", p.path, tok);
+					},
+					_ => input.SectionPatterns.push(w)
+				}
+				p.consume(",");
+			}
+		}
+
+		self.Inputs.push(input);
+		self.Inputs.len() - 1
+	}
+
+	/// the first input section description that section `name` of `file` matches.
+	/// returns an index into `Inputs`. archive members can be matched by
+	/// their own names, or by `archive:member`
+	pub fn MatchInputSection(&self, file: &File, name: &str) -> Option<usize> {
+		let mut names = vec![file.Name.clone()];
+		if let Some(parent) = &file.Parent {
+			names.push(format!("{}:{}", parent.Name, file.Name));
+		}
+		for i in 0..names.len() {
+			if let Some(base) = names[i].rsplit('/').next() {
+				names.push(base.into());
+			}
+		}
+		let matchFile = |pat: &str| names.iter().any(|n| GlobMatch(pat, n));
+
+		self.Inputs.iter().position(|input| {
+			matchFile(&input.FilePattern)
+				&& !input.ExcludeFiles.iter().any(|pat| matchFile(pat))
+				&& input.SectionPatterns.iter().any(|pat| GlobMatch(pat, name))
+		})
+	}
+
+	/// names of the output sections described in SECTIONS, in order
+	pub fn OutputSectionNames(&self) -> Vec<String> {
+		self.Commands.iter().filter_map(|cmd| match cmd {
+			Command::Output(desc) if desc.Name != "/DISCARD/" => Some(desc.Name.clone()),
+			_ => None
+		}).collect()
+	}
+
+	/// all the symbols assigned by the script
+	pub fn SymbolNames(&self) -> Vec<&Assignment> {
+		let mut v = vec![];
+		for cmd in &self.Commands {
+			match cmd {
+				Command::Assign(a) => v.push(a),
+				Command::Output(desc) => {
+					for c in &desc.Commands {
+						if let OutputSectionCommand::Assign(a) = c {
+							v.push(a);
+						}
+					}
+				},
+				_ => {}
+			}
+		}
+		v.retain(|a| a.Name != ".");
+		v
+	}
+
+	/// order the members of an output section as the script says
+	pub fn SortMembers(&self, members: &mut [Rc<RefCell<InputSection>>]) {
+		let priority = |isec: &InputSection| -> u64 {
+			let name = isec.Name();
+			name.rsplit('.').next().and_then(|n| n.parse().ok()).unwrap_or(65535)
+		};
+
+		members.sort_by(|a, b| {
+			let (a, b) = (a.borrow(), b.borrow());
+			// orphans with the same name as ours come last
+			let order = |isec: &InputSection| isec.ScriptOrder.unwrap_or(usize::MAX);
+			order(&a).cmp(&order(&b)).then_with(|| {
+				let kind = a.ScriptOrder.map_or(SortKind::None, |i| self.Inputs[i].Sort);
+				match kind {
+					SortKind::None => std::cmp::Ordering::Equal,
+					SortKind::Name => a.Name().cmp(&b.Name()),
+					SortKind::Alignment => b.P2Align.cmp(&a.P2Align),
+					SortKind::InitPriority => priority(&a).cmp(&priority(&b)),
+				}
+			})
+		});
+	}
+
+	fn FindRegion(&self, name: &str) -> &MemoryRegion {
+		match self.Memory.iter().find(|m| m.Name == name) {
+			Some(m) => m,
+			None => {
+				error!("{}: memory region '{}' not declared", self.Path, name);
+			}
+		}
+	}
+
+	pub fn Eval(&self, ctx: Option<&Context>, e: &Expr, dot: u64) -> u64 {
+		let eval = |e: &Expr| self.Eval(ctx, e, dot);
+		let ctx = || match ctx {
+			Some(ctx) => ctx,
+			None => {
+				error!("{}: the expression must be a constant", self.Path);
+			}
+		};
+
+		match e {
+			Expr::Num(n) => *n,
+			Expr::Dot => dot,
+			Expr::Symbol(name) => {
+				if let Some(val) = self.Values.get(name) {
+					return *val;
+				}
+				match ctx().SymbolMap.get(name) {
+					Some(sym) if sym.borrow().File.is_some() => sym.borrow().GetAddr(),
+					_ => {
+						error!("{}: undefined symbol '{}' referenced in expression", self.Path, name);
+					}
+				}
+			},
+			Expr::Unary(op, a) => {
+				let a = eval(a);
+				match op.as_str() {
+					"-" => a.wrapping_neg(),
+					"!" => (a == 0) as u64,
+					_ => !a,
+				}
+			},
+			Expr::Binary(op, a, b) => {
+				let (a, b) = (eval(a), eval(b));
+				match op.as_str() {
+					"+" => a.wrapping_add(b),
+					"-" => a.wrapping_sub(b),
+					"*" => a.wrapping_mul(b),
+					"/" | "%" if b == 0 => {
+						error!("{}: division by zero", self.Path);
+					},
+					"/" => a / b,
+					"%" => a % b,
+					"<<" => a.wrapping_shl(b as u32),
+					">>" => a.wrapping_shr(b as u32),
+					"&" => a & b,
+					"|" => a | b,
+					"^" => a ^ b,
+					"==" => (a == b) as u64,
+					"!=" => (a != b) as u64,
+					"<" => (a < b) as u64,
+					">" => (a > b) as u64,
+					"<=" => (a <= b) as u64,
+					">=" => (a >= b) as u64,
+					"&&" => (a != 0 && b != 0) as u64,
+					_ => (a != 0 || b != 0) as u64,
+				}
+			},
+			Expr::Cond(c, a, b) => if eval(c) != 0 { eval(a) } else { eval(b) },
+			Expr::Call(func, args) => {
+				let name = |i: usize| match &args[i] {
+					Expr::Symbol(s) => s.clone(),
+					_ => {
+						error!("{}: {} expects a name", self.Path, func);
+					}
+				};
+				if args.len() != FunctionArity(func).unwrap_or(args.len()) && func != "ALIGN" {
+					error!("{}: wrong number of arguments to {}", self.Path, func);
+				}
+
+				match func.as_str() {
+					"ALIGN" if args.len() == 1 => AlignUp(dot, eval(&args[0])),
+					"ALIGN" => AlignUp(eval(&args[0]), eval(&args[1])),
+					"ABSOLUTE" | "DATA_SEGMENT_END" => eval(&args[0]),
+					"DATA_SEGMENT_RELRO_END" => dot,
+					"DATA_SEGMENT_ALIGN" => {
+						let max = eval(&args[0]);
+						AlignUp(dot, max) + (dot & (max - 1))
+					},
+					"SEGMENT_START" => eval(&args[1]),
+					"MAX" => eval(&args[0]).max(eval(&args[1])),
+					"MIN" => eval(&args[0]).min(eval(&args[1])),
+					"LOG2CEIL" => eval(&args[0]).max(1).next_power_of_two().trailing_zeros() as u64,
+					"ORIGIN" => eval(&self.FindRegion(&name(0)).Origin),
+					"LENGTH" => eval(&self.FindRegion(&name(0)).Length),
+					"CONSTANT" => match name(0).as_str() {
+						"MAXPAGESIZE" | "COMMONPAGESIZE" => PAGESIZE,
+						other => {
+							error!("{}: unknown constant {}", self.Path, other);
+						}
+					},
+					"SIZEOF_HEADERS" => (EHDR_SIZE + ctx().Phdr.Shdr.Size) as u64,
+					"DEFINED" => {
+						let name = name(0);
+						(self.Values.contains_key(&name) || IsDefinedByUser(ctx(), &name)) as u64
+					},
+					"ADDR" | "LOADADDR" | "SIZEOF" | "ALIGNOF" => {
+						let name = name(0);
+						let (addr, size, align) = SectionSpan(ctx(), &name).unwrap_or((0, 0, 1));
+						match func.as_str() {
+							"ADDR" => addr,
+							"LOADADDR" => addr.wrapping_add(*self.LoadOffsets.get(&name).unwrap_or(&0)),
+							"SIZEOF" => size,
+							_ => align,
+						}
+					},
+					_ => {
+						error!("{}: unknown function {}", self.Path, func);
+					}
+				}
+			}
+		}
+	}
+
+	fn Assign(&mut self, ctx: &Context, a: &Assignment, dot: &mut u64) {
+		// PROVIDE never overrides real definitions
+		if a.Provide && IsDefinedByUser(ctx, &a.Name) {
+			return;
+		}
+
+		let val = self.Eval(Some(ctx), &a.Expr, *dot);
+		if a.Name == "." {
+			*dot = val;
+		}
+		else {
+			self.Values.insert(a.Name.clone(), val);
+		}
+	}
+
+	/// assign addresses to the alloc chunks in the order of SECTIONS.
+	/// chunks not mentioned(orphans) are placed where `SortOutputSections` put them
+	///
+	/// expressions may refer to things defined later, like `LOADADDR(.data)`
+	/// before `.data`. they get the values from the previous pass, so repeat
+	/// until nothing changes
+	pub fn AssignAddresses(&mut self, ctx: &Context) {
+		let snapshot = |script: &LinkerScript| {
+			let addrs: Vec<u64> = ctx.Chunks.iter().map(|c| ptr2ref_dyn(*c).GetShdr().Addr).collect();
+			(script.Values.clone(), script.LoadOffsets.clone(), addrs)
+		};
+
+		for _ in 0..4 {
+			let before = snapshot(self);
+			self.AssignAddressesOnce(ctx);
+			if snapshot(self) == before {
+				break;
+			}
+		}
+	}
+
+	fn AssignAddressesOnce(&mut self, ctx: &Context) {
+		self.Segments.clear();
+		self.Errors.clear();
+
+		let chunks: Vec<*mut dyn Chunker> = ctx.Chunks.iter()
+			.filter(|c| ptr2ref_dyn(**c).GetShdr().Flags & abi::SHF_ALLOC as u64 != 0)
+			.copied().collect();
+		let mut placed = vec![false; chunks.len()];
+		// region name -> the next free address
+		let mut cursors: BTreeMap<String, u64> = BTreeMap::new();
+		let mut dot = 0;
+		let mut next = 0;
+		let mut phdrs: Vec<String> = vec![];
+		// (vma region, lma - vma) of the last output section
+		let mut lastLoadOffset: (Option<String>, u64) = (None, 0);
+
+		let commands = self.Commands.clone();
+		for cmd in &commands {
+			let desc = match cmd {
+				Command::Assign(a) => {
+					self.Assign(ctx, a, &mut dot);
+					continue;
+				},
+				Command::Assert(e, msg) => {
+					if self.Eval(Some(ctx), e, dot) == 0 {
+						self.Errors.push(msg.clone());
+					}
+					continue;
+				},
+				Command::Output(desc) => desc,
+			};
+
+			let idxs: Vec<usize> = (0..chunks.len())
+				.filter(|i| !placed[*i] && *ptr2ref_dyn(chunks[*i]).GetName() == desc.Name)
+				.collect();
+			if desc.Name == "/DISCARD/" || idxs.is_empty() {
+				// empty sections are dropped, but the symbols in them are still defined
+				for c in &desc.Commands {
+					if let OutputSectionCommand::Assign(a) = c {
+						self.Assign(ctx, a, &mut dot);
+					}
+				}
+				continue;
+			}
+
+			// orphans sorted before this one
+			while next < idxs[0] {
+				if !placed[next] {
+					placed[next] = true;
+					self.PlaceOrphan(ptr2ref_dyn(chunks[next]), &mut dot, &phdrs);
+				}
+				next += 1;
+			}
+
+			let first = ptr2ref_dyn(chunks[idxs[0]]);
+			// --section-start wins over the script
+			let start = ctx.Args.SectionStart.get(&desc.Name).copied();
+			let mut addr = match (&desc.Addr, &desc.Region) {
+				_ if start.is_some() => start.unwrap(),
+				(Some(e), _) => self.Eval(Some(ctx), e, dot),
+				(None, Some(r)) => {
+					let origin = self.Eval(Some(ctx), &self.FindRegion(r).Origin, dot);
+					*cursors.entry(r.clone()).or_insert(origin)
+				},
+				(None, None) => dot,
+			};
+			if desc.Addr.is_none() && start.is_none() {
+				addr = AlignUp(addr, first.GetShdr().AddrAlign);
+			}
+			if let Some(align) = &desc.Align {
+				addr = AlignUp(addr, self.Eval(Some(ctx), align, dot));
+			}
+
+			dot = addr;
+			let mut inner = false;
+			for i in &idxs {
+				placed[*i] = true;
+				let c = ptr2ref_dyn(chunks[*i]);
+				dot = AlignUp(dot, c.GetShdr().AddrAlign);
+				c.GetShdr().Addr = dot;
+
+				let osec = ctx.OutputSections.iter()
+					.find(|o| std::ptr::eq(o.as_ptr() as *const u8, chunks[*i] as *const u8));
+				match osec {
+					Some(osec) if !inner => {
+						inner = true;
+						let size = self.LayoutOutputSection(ctx, &mut osec.borrow_mut(), desc, dot);
+						c.GetShdr().Size = size as usize;
+					},
+					_ => {}
+				}
+				if !isTbss(c) {
+					dot += c.GetShdr().Size as u64;
+				}
+			}
+			if !inner {
+				// only merged sections with this name
+				for c in &desc.Commands {
+					if let OutputSectionCommand::Assign(a) = c {
+						self.Assign(ctx, a, &mut dot);
+					}
+				}
+			}
+			let end = dot;
+			let isBss = first.GetShdr().Type == abi::SHT_NOBITS;
+
+			if let Some(r) = &desc.Region {
+				cursors.insert(r.clone(), end);
+				let region = self.FindRegion(r);
+				let limit = self.Eval(Some(ctx), &region.Origin, dot) + self.Eval(Some(ctx), &region.Length, dot);
+				if end > limit {
+					self.Errors.push(format!("section '{}' will not fit in region '{}': overflowed by {} bytes",
+						desc.Name, r, end - limit));
+				}
+			}
+
+			// the load address
+			let lma = match (&desc.Lma, &desc.LmaRegion) {
+				(Some(e), _) => Some(self.Eval(Some(ctx), e, dot)),
+				(None, Some(r)) => {
+					let origin = self.Eval(Some(ctx), &self.FindRegion(r).Origin, dot);
+					let lma = AlignUp(*cursors.entry(r.clone()).or_insert(origin), first.GetShdr().AddrAlign);
+					if !isBss {
+						cursors.insert(r.clone(), lma + (end - addr));
+					}
+					Some(lma)
+				},
+				// like GNU ld, keep the distance of the previous section if they share a region
+				(None, None) if lastLoadOffset.0.is_some() && lastLoadOffset.0 == desc.Region => Some(addr.wrapping_add(lastLoadOffset.1)),
+				(None, None) => None,
+			};
+			let offset = lma.map_or(0, |lma| lma.wrapping_sub(addr));
+			self.LoadOffsets.insert(desc.Name.clone(), offset);
+			lastLoadOffset = (desc.Region.clone(), offset);
+
+			if !desc.Phdrs.is_empty() {
+				phdrs = desc.Phdrs.clone();
+			}
+			self.Segments.insert(desc.Name.clone(), phdrs.clone());
+
+			// .tbss doesn't take any space
+			if isTbss(first) {
+				dot = addr;
+			}
+		}
+
+		for i in 0..chunks.len() {
+			if !placed[i] {
+				self.PlaceOrphan(ptr2ref_dyn(chunks[i]), &mut dot, &phdrs);
+			}
+		}
+	}
+
+	/// place the members and run the commands of an output section at `start`.
+	/// returns the size
+	fn LayoutOutputSection(&mut self, ctx: &Context, osec: &mut OutputSection, desc: &OutputSectionDesc, start: u64) -> u64 {
+		let mut offset = 0;
+		let mut m = 0;
+		let place = |isec: &Rc<RefCell<InputSection>>, offset: &mut u64| {
+			let mut isec = isec.borrow_mut();
+			*offset = AlignUp(*offset, 1 << isec.P2Align);
+			isec.Offset = *offset as usize;
+			*offset += isec.ShSize as u64;
+		};
+
+		for cmd in &desc.Commands {
+			match cmd {
+				OutputSectionCommand::Input(i) => {
+					while m < osec.Members.len() && osec.Members[m].borrow().ScriptOrder == Some(*i) {
+						place(&osec.Members[m], &mut offset);
+						m += 1;
+					}
+				},
+				OutputSectionCommand::Assign(a) => {
+					let mut dot = start + offset;
+					self.Assign(ctx, a, &mut dot);
+					if dot < start + offset {
+						error!("{}: unable to move location counter backward in {}", self.Path, desc.Name);
+					}
+					offset = dot - start;
+				},
+				OutputSectionCommand::Assert(e, msg) => {
+					if self.Eval(Some(ctx), e, start + offset) == 0 {
+						self.Errors.push(msg.clone());
+					}
+				},
+			}
+		}
+
+		// sections matched by other descriptions, if any
+		while m < osec.Members.len() {
+			place(&osec.Members[m], &mut offset);
+			m += 1;
+		}
+		offset
+	}
+
+	fn PlaceOrphan(&mut self, c: &mut dyn Chunker, dot: &mut u64, phdrs: &[String]) {
+		*dot = AlignUp(*dot, c.GetShdr().AddrAlign);
+		c.GetShdr().Addr = *dot;
+		if !isTbss(c) {
+			*dot += c.GetShdr().Size as u64;
+		}
+		self.Segments.insert(c.GetName().clone(), phdrs.to_vec());
+	}
+
+	/// program headers listed in PHDRS
+	pub fn CreatePhdrs(&self, ctx: &mut Context) -> Vec<Phdr> {
+		let mut vec = vec![];
+		for desc in &self.Phdrs {
+			let chunks: Vec<&mut dyn Chunker> = ctx.Chunks.iter()
+				.map(|c| ptr2ref_dyn(*c))
+				.filter(|c| self.Segments.get(c.GetName()).is_some_and(|s| s.contains(&desc.Name)))
+				.filter_map(|c| {
+					let alloc = c.GetShdr().Flags & abi::SHF_ALLOC as u64 != 0;
+					// .tbss only exists in PT_TLS, and PT_TLS has nothing else
+					let skip = !alloc || match desc.Type {
+						abi::PT_TLS => c.GetShdr().Flags & abi::SHF_TLS as u64 == 0,
+						_ => isTbss(c)
+					};
+					(!skip).then_some(c)
+				})
+				.collect();
+
+			let mut phdr = Phdr { Type: desc.Type, Flags: abi::PF_R, Align: 1, ..Default::default() };
+			for (i, c) in chunks.into_iter().enumerate() {
+				let flags = c.toPhdrFlags();
+				let shdr = c.GetShdr().clone();
+				if i == 0 {
+					phdr.Offset = shdr.Offset as u64;
+					phdr.VAddr = shdr.Addr;
+					phdr.PAddr = shdr.Addr.wrapping_add(*self.LoadOffsets.get(c.GetName()).unwrap_or(&0));
+					phdr.Flags = 0;
+				}
+				phdr.Flags |= flags;
+				phdr.Align = phdr.Align.max(shdr.AddrAlign);
+				if shdr.Type != abi::SHT_NOBITS {
+					phdr.FileSize = phdr.FileSize.max(shdr.Offset as u64 + shdr.Size as u64 - phdr.Offset);
+				}
+				phdr.MemSize = phdr.MemSize.max(shdr.Addr + shdr.Size as u64 - phdr.VAddr);
+			}
+
+			if let Some(flags) = desc.Flags {
+				phdr.Flags = flags;
+			}
+			if desc.Type == abi::PT_LOAD {
+				phdr.Align = phdr.Align.max(PAGESIZE);
+			}
+			if desc.Type == abi::PT_TLS {
+				ctx.TpAddr = phdr.VAddr;
+			}
+			vec.push(phdr);
+		}
+		vec
+	}
+}
+
+/// defined by an input file rather than by us
+fn IsDefinedByUser(ctx: &Context, name: &str) -> bool {
+	ctx.SymbolMap.get(name).is_some_and(|s| {
+		s.borrow().File.as_ref().is_some_and(|f| !Rc::ptr_eq(f, &ctx.InternalObj))
+	})
+}
+
+/// (address, size, alignment) of the chunks named `name`
+fn SectionSpan(ctx: &Context, name: &str) -> Option<(u64, u64, u64)> {
+	let mut span: Option<(u64, u64, u64)> = None;
+	for c in &ctx.Chunks {
+		let c = ptr2ref_dyn(*c);
+		if c.GetName() != name {
+			continue;
+		}
+		let shdr = c.GetShdr();
+		let (start, end) = (shdr.Addr, shdr.Addr + shdr.Size as u64);
+		span = Some(match span {
+			None => (start, end, shdr.AddrAlign),
+			Some((s, e, a)) => (s.min(start), e.max(end), a.max(shdr.AddrAlign))
+		});
+	}
+	span.map(|(start, end, align)| (start, end - start, align))
+}
+
+fn FunctionArity(name: &str) -> Option<usize> {
+	Some(match name {
+		"SIZEOF_HEADERS" => 0,
+		"ABSOLUTE" | "DATA_SEGMENT_END" | "LOG2CEIL" | "ORIGIN" | "LENGTH" | "CONSTANT" |
+		"DEFINED" | "ADDR" | "LOADADDR" | "SIZEOF" | "ALIGNOF" => 1,
+		"DATA_SEGMENT_ALIGN" | "DATA_SEGMENT_RELRO_END" | "SEGMENT_START" | "MAX" | "MIN" => 2,
+		_ => return None
+	})
+}
+
+/// unlike `AlignTo`, `align` doesn't have to be a power of 2
+fn AlignUp(val: u64, align: u64) -> u64 {
+	match align {
+		0 | 1 => val,
+		_ => val.div_ceil(align) * align
+	}
+}
+
+/// shell-style wildcards: `*`, `?` and `[...]`
+pub fn GlobMatch(pat: &str, s: &str) -> bool {
+	let (p, s) = (pat.as_bytes(), s.as_bytes());
+	// the position to go back to when a `*` has to eat one more character
+	let (mut pi, mut si) = (0, 0);
+	let mut star: Option<(usize, usize)> = None;
+
+	while si < s.len() {
+		if pi < p.len() {
+			match p[pi] {
+				b'*' => {
+					star = Some((pi, si));
+					pi += 1;
+					continue;
+				},
+				b'?' => {
+					pi += 1;
+					si += 1;
+					continue;
+				},
+				b'[' => {
+					if let Some((matched, len)) = MatchClass(&p[pi..], s[si]) {
+						if matched {
+							pi += len;
+							si += 1;
+							continue;
+						}
+					}
+					else if s[si] == b'[' {
+						pi += 1;
+						si += 1;
+						continue;
+					}
+				},
+				c if c == s[si] => {
+					pi += 1;
+					si += 1;
+					continue;
+				},
+				_ => {}
+			}
+		}
+
+		match star {
+			Some((spi, ssi)) => {
+				pi = spi + 1;
+				si = ssi + 1;
+				star = Some((spi, ssi + 1));
+			},
+			None => return false
+		}
+	}
+
+	p[pi..].iter().all(|c| *c == b'*')
+}
+
+/// match `c` against a `[...]` class at the start of `p`.
+/// returns (matched, length of the class), or None if the class is unterminated
+fn MatchClass(p: &[u8], c: u8) -> Option<(bool, usize)> {
+	let mut i = 1;
+	let negate = i < p.len() && (p[i] == b'!' || p[i] == b'^');
+	if negate {
+		i += 1;
+	}
+
+	let mut matched = false;
+	let mut first = true;
+	while i < p.len() && (p[i] != b']' || first) {
+		first = false;
+		if i + 2 < p.len() && p[i + 1] == b'-' && p[i + 2] != b']' {
+			matched |= p[i] <= c && c <= p[i + 2];
+			i += 3;
+		}
+		else {
+			matched |= p[i] == c;
+			i += 1;
+		}
+	}
+
+	if i >= p.len() {
+		return None;
+	}
+	Some((matched != negate, i + 1))
+}
+
+/// a hand-written tokenizer and parser. the syntax is context sensitive:
+/// `*` and `/` are operators in expressions, but are part of the names in
+/// input section descriptions. so the parser asks for what it expects
+struct Parser<'a> {
+	src:	&'a [u8],
+	pos:	usize,
+	path:	&'a str,
+}
+
+const OPERATORS: [&str; 37] = [
+	"<<=", ">>=",
+	"<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=", "*=", "/=", "&=", "|=",
+	"+", "-", "*", "/", "%", "&", "|", "^", "~", "!", "<", ">", "?", ":", "=", "(", ")", "{", "}", ";", ",",
+];
+
+const ASSIGNMENTS: [&str; 9] = ["=", "+=", "-=", "*=", "/=", "<<=", ">>=", "&=", "|="];
+
+impl<'a> Parser<'a> {
+	fn skipSpace(&mut self) {
+		loop {
+			while self.pos < self.src.len() && self.src[self.pos].is_ascii_whitespace() {
+				self.pos += 1;
+			}
+			if self.src[self.pos..].starts_with(b"/*") {
+				match self.src[self.pos + 2..].windows(2).position(|w| w == b"*/") {
+					Some(end) => self.pos += end + 4,
+					None => {
+						error!("{}: unterminated comment", self.path);
+					}
+				}
+				continue;
+			}
+			break;
+		}
+	}
+
+	fn atEnd(&mut self) -> bool {
+		self.skipSpace();
+		self.pos >= self.src.len()
+	}
+
+	fn isNameChar(c: u8) -> bool {
+		c.is_ascii_alphanumeric() || c == b'_' || c == b'.' || c == b'$'
+	}
+
+	/// a token in expressions
+	fn token(&mut self) -> String {
+		if self.atEnd() {
+			error!("{}: unexpected end of file", self.path);
+		}
+
+		let rest = &self.src[self.pos..];
+		let len = if rest[0] == b'"' {
+			match rest[1..].iter().position(|c| *c == b'"') {
+				Some(end) => end + 2,
+				None => {
+					error!("{}: unterminated string", self.path);
+				}
+			}
+		}
+		else if Self::isNameChar(rest[0]) {
+			rest.iter().position(|c| !Self::isNameChar(*c)).unwrap_or(rest.len())
+		}
+		else {
+			match OPERATORS.iter().find(|op| rest.starts_with(op.as_bytes())) {
+				Some(op) => op.len(),
+				None => {
+					error!("{}: unexpected character '{}'", self.path, rest[0] as char);
+				}
+			}
+		};
+
+		let tok = String::from_utf8_lossy(&rest[..len]).to_string();
+		self.pos += len;
+		tok
+	}
+
+	fn peek(&mut self) -> String {
+		if self.atEnd() {
+			return String::new();
+		}
+		let pos = self.pos;
+		let tok = self.token();
+		self.pos = pos;
+		tok
+	}
+
+	/// a name, which may be a file name or a wildcard pattern
+	fn word(&mut self) -> String {
+		self.skipSpace();
+		let rest = &self.src[self.pos..];
+		if rest.first() == Some(&b'"') {
+			let tok = self.token();
+			return tok[1..tok.len() - 1].into();
+		}
+
+		let len = rest.iter()
+			.position(|c| c.is_ascii_whitespace() || b"(){};,:=\"".contains(c))
+			.unwrap_or(rest.len());
+		let w = String::from_utf8_lossy(&rest[..len]).to_string();
+		self.pos += len;
+		w
+	}
+
+	fn peekWord(&mut self) -> String {
+		let pos = self.pos;
+		let w = self.word();
+		self.pos = pos;
+		w
+	}
+
+	fn consume(&mut self, tok: &str) -> bool {
+		if self.peek() == tok {
+			self.token();
+			return true;
+		}
+		false
+	}
+
+	fn expect(&mut self, tok: &str) {
+		let t = self.token();
+		if t != tok {
+			error!("{}: expected '{}', but got '{}'", self.path, tok, t);
+		}
+	}
+
+	fn expectOneOf(&mut self, toks: &[&str]) {
+		let t = self.token();
+		if !toks.contains(&t.as_str()) {
+			error!("{}: expected one of {:?}, but got '{}'", self.path, toks, t);
+		}
+	}
+
+	fn skipParens(&mut self) {
+		self.expect("(");
+		let mut depth = 1;
+		while depth > 0 {
+			match self.token().as_str() {
+				"(" => depth += 1,
+				")" => depth -= 1,
+				_ => {}
+			}
+		}
+	}
+
+	/// `(pattern pattern ...)`
+	fn patternList(&mut self) -> Vec<String> {
+		let mut v = vec![];
+		self.expect("(");
+		while !self.consume(")") {
+			let w = self.word();
+			if w.is_empty() {
+				let tok = self.peek();
+				error!("{}: unexpected '{}' in pattern list", self.path, tok);
+			}
+			// nested lists like SORT_BY_NAME(SORT_BY_ALIGNMENT(.text.*))
+			if self.peek() == "(" {
+				v.push(w);
+				v.extend(self.patternList());
+			}
+			else {
+				v.push(w);
+			}
+			self.consume(",");
+		}
+		v
+	}
+
+	/// (NOLOAD) and friends after an output section name
+	fn isSectionType(&mut self) -> bool {
+		let pos = self.pos;
+		let res = self.consume("(") &&
+			["NOLOAD", "COPY", "INFO", "DSECT", "OVERLAY", "READONLY"].contains(&self.word().as_str()) &&
+			self.consume(")");
+		self.pos = pos;
+		res
+	}
+
+	fn isAssignment(&mut self) -> bool {
+		let pos = self.pos;
+		let w = self.word();
+		let res = ["PROVIDE", "PROVIDE_HIDDEN", "HIDDEN"].contains(&w.as_str()) ||
+			(!w.is_empty() && ASSIGNMENTS.contains(&self.peek().as_str()));
+		self.pos = pos;
+		res
+	}
+
+	fn assignment(&mut self) -> Assignment {
+		let name = self.word();
+		if ["PROVIDE", "PROVIDE_HIDDEN", "HIDDEN"].contains(&name.as_str()) {
+			self.expect("(");
+			let mut a = self.assignment();
+			self.expect(")");
+			a.Provide = name != "HIDDEN";
+			a.Hidden = name != "PROVIDE";
+			return a;
+		}
+
+		let op = self.token();
+		if !ASSIGNMENTS.contains(&op.as_str()) {
+			error!("{}: expected an assignment after '{}', but got '{}'", self.path, name, op);
+		}
+
+		let mut expr = self.expr();
+		if op != "=" {
+			let lhs = match name.as_str() {
+				"." => Expr::Dot,
+				_ => Expr::Symbol(name.clone())
+			};
+			expr = Expr::Binary(op[..op.len() - 1].into(), Box::new(lhs), Box::new(expr));
+		}
+		Assignment { Name: name, Expr: expr, Provide: false, Hidden: false }
+	}
+
+	/// ASSERT(expr, "message")
+	fn assert(&mut self) -> (Expr, String) {
+		self.expect("ASSERT");
+		self.expect("(");
+		let e = self.expr();
+		self.expect(",");
+		let msg = self.word();
+		self.expect(")");
+		self.consume(";");
+		(e, msg)
+	}
+
+	fn expr(&mut self) -> Expr {
+		let cond = self.binary(0);
+		if !self.consume("?") {
+			return cond;
+		}
+		let a = self.expr();
+		self.expect(":");
+		let b = self.expr();
+		Expr::Cond(Box::new(cond), Box::new(a), Box::new(b))
+	}
+
+	fn precedence(op: &str) -> Option<u8> {
+		Some(match op {
+			"||" => 1,
+			"&&" => 2,
+			"|" => 3,
+			"^" => 4,
+			"&" => 5,
+			"==" | "!=" => 6,
+			"<" | ">" | "<=" | ">=" => 7,
+			"<<" | ">>" => 8,
+			"+" | "-" => 9,
+			"*" | "/" | "%" => 10,
+			_ => return None
+		})
+	}
+
+	fn binary(&mut self, minPrec: u8) -> Expr {
+		let mut lhs = self.unary();
+		loop {
+			let op = self.peek();
+			let prec = match Self::precedence(&op) {
+				Some(prec) if prec >= minPrec => prec,
+				_ => break
+			};
+			self.token();
+			let rhs = self.binary(prec + 1);
+			lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+		}
+		lhs
+	}
+
+	fn unary(&mut self) -> Expr {
+		let tok = self.token();
+		match tok.as_str() {
+			"-" | "!" | "~" => Expr::Unary(tok, Box::new(self.unary())),
+			"+" => self.unary(),
+			"(" => {
+				let e = self.expr();
+				self.expect(")");
+				e
+			},
+			"." => Expr::Dot,
+			_ if tok.as_bytes()[0].is_ascii_digit() => Expr::Num(self.number(&tok)),
+			_ if Self::isNameChar(tok.as_bytes()[0]) => {
+				if tok == "SIZEOF_HEADERS" {
+					return Expr::Call(tok, vec![]);
+				}
+				if !self.consume("(") {
+					return Expr::Symbol(tok);
+				}
+
+				let mut args = vec![];
+				while !self.consume(")") {
+					args.push(self.expr());
+					self.consume(",");
+				}
+				Expr::Call(tok, args)
+			},
+			_ => {
+				error!("{}: unexpected '{}' in expression", self.path, tok);
+			}
+		}
+	}
+
+	/// 0x1000, 4K, 1M, 017
+	fn number(&self, tok: &str) -> u64 {
+		let (digits, scale) = match tok.as_bytes()[tok.len() - 1] {
+			b'K' | b'k' => (&tok[..tok.len() - 1], 1024),
+			b'M' | b'm' => (&tok[..tok.len() - 1], 1024 * 1024),
+			_ => (tok, 1)
+		};
+
+		let val = if digits.starts_with("0x") || digits.starts_with("0X") {
+			u64::from_str_radix(&digits[2..], 16).ok()
+		}
+		else if digits.len() > 1 && digits.starts_with('0') {
+			u64::from_str_radix(&digits[1..], 8).ok()
+		}
+		else {
+			digits.parse().ok()
+		};
+
+		match val {
+			Some(v) => v * scale,
+			None => {
+				error!("{}: invalid number {}", self.path, tok);
+			}
+		}
+	}
+}
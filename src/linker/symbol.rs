@@ -1,5 +1,6 @@
 use super::elf::Sym;
 use super::inputsections::{InputSection, SectionFragment};
+use super::output::OutputPlt;
 use super::common::*;
 
 // an easier-to-use abstraction for Sym
@@ -11,6 +12,23 @@ pub struct  Symbol {
 	pub Value:				u64,
 	pub SymIdx:				usize,
 	pub SectionFragment:	Option<Rc<RefCell<SectionFragment>>>,
+	/// merged from all the references. the most restrictive one wins
+	pub Visibility:			u8,
+	/// the slot in .got, for symbols referred to through the GOT
+	pub GotIdx:				Option<usize>,
+	/// the entry in .plt, for functions in shared libraries
+	pub PltIdx:				Option<usize>,
+	pub DynsymIdx:			Option<usize>,
+	/// the PLT entry is the address of the function, as non-PIC code takes it directly
+	pub IsCanonical:		bool,
+	/// data of a shared library copied into .dynbss, as non-PIC code can't reach it
+	pub HasCopyrel:			bool,
+	/// the entry in .iplt, for IFUNC symbols
+	pub IpltIdx:			Option<usize>,
+	/// the address of the .iplt entry, set after layout
+	pub IpltAddr:			u64,
+	/// defined by the linker as a plain number, not an address in the image
+	pub IsAbsValue:			bool,
 }
 
 impl Symbol {
@@ -32,6 +50,43 @@ impl Symbol {
 	}
 
 
+	/// the final virtual address of this symbol.
+	/// only meaningful after `SetOutputSectionOffsets`
+	pub fn GetAddr(&self) -> u64 {
+		// an IFUNC is called through its .iplt entry, which jumps to
+		// the function its resolver has chosen at startup
+		if self.IpltIdx.is_some() {
+			return self.IpltAddr;
+		}
+		self.GetDefinedAddr()
+	}
+
+	/// where the symbol is defined. the resolver, for IFUNC symbols
+	pub fn GetDefinedAddr(&self) -> u64 {
+		if let Some(frag) = &self.SectionFragment {
+			return frag.borrow().GetAddr() + self.Value;
+		}
+		if let Some(isec) = &self.InputSection {
+			return isec.borrow().GetAddr() + self.Value;
+		}
+		// absolute symbols. undefined ones simply get 0
+		self.Value
+	}
+
+	pub fn MergeVisibility(&mut self, vis: u8) {
+		// STV_DEFAULT is 0, but it's the least restrictive one
+		let rank = |v: u8| if v == abi::STV_DEFAULT { 4 } else { v };
+		if rank(vis) < rank(self.Visibility) {
+			self.Visibility = vis;
+		}
+	}
+
+	/// hidden and internal symbols are not visible outside of the output file.
+	/// they become local in .symtab, and are never exported dynamically
+	pub fn IsHidden(&self) -> bool {
+		self.Visibility == abi::STV_HIDDEN || self.Visibility == abi::STV_INTERNAL
+	}
+
 	pub fn GetSymbolByName(ctx: &mut Context, name: &str) -> Rc<RefCell<Symbol>> {
 		if let Some(sym) = ctx.SymbolMap.get(name.into()) {
 			return sym.clone();
@@ -41,6 +96,45 @@ impl Symbol {
 		return newSym.clone();
 	}
 
+	/// the symbol is defined by a shared library
+	pub fn IsImported(&self) -> bool {
+		self.File.as_ref().is_some_and(|f| f.borrow().IsDso())
+	}
+
+	/// a function whose address is chosen at runtime by calling its resolver
+	pub fn IsIfunc(&self) -> bool {
+		self.File.is_some() && !self.IsImported() && self.ElfSym().Type() == abi::STT_GNU_IFUNC
+	}
+
+	pub fn IsTls(&self) -> bool {
+		self.File.is_some() && self.ElfSym().Type() == abi::STT_TLS
+	}
+
+	/// the offset from the thread pointer. the TLS block of the executable
+	/// starts right at tp, as RISC-V uses the variant I layout
+	pub fn GetTpOffset(&self, ctx: &Context) -> u64 {
+		self.GetAddr().wrapping_sub(ctx.TpAddr)
+	}
+
+	pub fn GetGotAddr(&self, ctx: &Context) -> u64 {
+		ctx.Got.Shdr.Addr + (self.GotIdx.unwrap() * ctx.Got.Shdr.EntSize) as u64
+	}
+
+	pub fn GetPltAddr(&self, ctx: &Context) -> u64 {
+		ctx.Plt.Shdr.Addr + (OutputPlt::HEADER_SIZE + self.PltIdx.unwrap() * OutputPlt::ENTRY_SIZE) as u64
+	}
+
+	/// the value doesn't move with the image. undefined weak symbols stay 0,
+	/// and the symbols defined by the linker are addresses in the image,
+	/// except the bounds of absent sections
+	pub fn IsAbsolute(&self, ctx: &Context) -> bool {
+		match &self.File {
+			None => true,
+			Some(file) if Rc::ptr_eq(file, &ctx.InternalObj) => self.IsAbsValue,
+			Some(_) => !self.IsImported() && self.ElfSym().IsAbs(),
+		}
+	}
+
 	pub fn FileAlive(&self) -> bool {
 		// external global symbols?
         if self.File.is_none() {
@@ -6,7 +6,7 @@ mod utils;
 mod linker;
 mod debug;
 
-use linker::context::Context;
+use linker::context::{Context, UnresolvedSymbols, Icf, HashStyle};
 use linker::elf::GetMachineType;
 use linker::file::File;
 use std::cell::RefCell;
@@ -14,6 +14,7 @@ use std::io::Write;
 
 use crate::linker::elf::{MachineType, checkMagic, Ehdr};
 use crate::linker::passes;
+use crate::linker::script::LinkerScript;
 
 fn main() {
     //std::env::args().into_iter().for_each(|x| info!("{}", x));
@@ -41,16 +42,28 @@ fn main() {
     linker::file::ReadInputFiles(&mut ctx, remaining);
     passes::CreateInternalFile(&mut ctx);   debug!("before: #objs = {}", ctx.Objs.len());
     passes::ResolveSymbols(&mut ctx);       debug!("after: #objs = {}", ctx.Objs.len());
+    passes::CheckUndefinedSymbols(&mut ctx);
+    passes::CheckDuplicateSymbols(&mut ctx);
     passes::RegisterSectionPieces(&mut ctx);
     passes::ConvertCommonSymbols(&mut ctx);
+    if ctx.Args.GcSections {
+        passes::GcSections(&mut ctx);
+    }
+    if ctx.Args.Icf != Icf::None {
+        passes::IcfSections(&mut ctx);
+    }
+    passes::CombineEhFrames(&mut ctx);
     passes::ComputeMergedSectionSizes(&mut ctx);
     passes::CreateSyntheticSections(&mut ctx);
     passes::BinSections(&mut ctx);
     let chunks = passes::CollectOutputSections(&mut ctx);
     ctx.Chunks.extend(chunks);
+    passes::MarkAbsentSectionBounds(&mut ctx);
+    passes::ScanRelocations(&mut ctx);
 
     passes::ComputeSectionSizes(&mut ctx);
     passes::SortOutputSections(&mut ctx);
+    passes::SetSectionIndices(&mut ctx);
 
     let ctx_ptr = std::ptr::addr_of_mut!(ctx);
     // mark
@@ -59,7 +72,18 @@ fn main() {
         c.UpdateShdr(ctx_ptr);
     }
 
+    passes::SetOutputSectionOffsets(&mut ctx);
+    passes::FixSyntheticSymbols(&mut ctx);
+    passes::SetPltSymbolAddrs(&mut ctx);
+
+    // relaxation needs the addresses, and shrinks sections.
+    // so the layout is done once more
+    passes::RelaxSections(&mut ctx);
+    passes::ComputeSectionSizes(&mut ctx);
     let fileSz = passes::SetOutputSectionOffsets(&mut ctx);
+    passes::FixSyntheticSymbols(&mut ctx);
+    passes::SetPltSymbolAddrs(&mut ctx);
+    passes::CheckLayout(&mut ctx);
     debug!("file size = {fileSz}");
 
     let mut f = std::fs::OpenOptions::new()
@@ -94,7 +118,9 @@ pub fn parseArgs(ctx: &mut Box<Context>) -> Vec<String> {
         }
     };
 
-    // options that need arguments. this will consume both the option and its arg(if has)
+    // options that need arguments. this will consume both the option and its arg(if has).
+    // a single letter one also matches when joined with its arg, e.g. -lfoo, so long
+    // options sharing its first letter must be tried before it
     let readArg = |name: &str| -> bool {
         let mut args = args.borrow_mut();
         let mut arg = arg.borrow_mut();
@@ -134,14 +160,27 @@ pub fn parseArgs(ctx: &mut Box<Context>) -> Vec<String> {
         return false;
     };
 
+    // like GNU ld, section addresses are always in hex
+    let parseAddr = |opt: &str, s: &str| -> u64 {
+        let digits = s.strip_prefix("0x").or(s.strip_prefix("0X")).unwrap_or(s);
+        match u64::from_str_radix(digits, 16) {
+            Ok(addr) => addr,
+            Err(_) => {
+                error!("invalid {} argument: {}", opt, s);
+            }
+        }
+    };
+
     let mut remaining = vec![];
+    let mut entrySet = false;
+    let mut imageBaseSet = false;
     while args.borrow_mut().len() > 0 {
         if readFlag("help") {
             info!("usage: {} [options] file...", std::env::args().next().unwrap());
             std::process::exit(0);
         }
 
-        if readArg("o") || readArg("output") {
+        if readArg("output") || readArg("o") {
             ctx.Args.Output = arg.borrow().clone();
         }
         else if readArg("m") {
@@ -153,22 +192,172 @@ pub fn parseArgs(ctx: &mut Box<Context>) -> Vec<String> {
                 error!("unknown -m argument: {}", arch);
             }
         }
+        else if readArg("image-base") {
+            ctx.Args.ImageBase = match utils::ParseNumber(&arg.borrow()) {
+                Some(addr) => addr,
+                None => {
+                    error!("invalid --image-base argument: {}", arg.borrow());
+                }
+            };
+            imageBaseSet = true;
+        }
+        else if readFlag("pie") {
+            ctx.Args.Pie = true;
+        }
+        else if readFlag("static-pie") {
+            ctx.Args.Pie = true;
+            remaining.push("-Bstatic".into());
+        }
+        else if readFlag("no-pie") {
+            ctx.Args.Pie = false;
+        }
+        // must come before -T, which would take `-Ttext=...` as a script
+        else if let Some(name) = ["text", "data", "bss"].iter().find(|name| readArg(&format!("T{}", name))) {
+            ctx.Args.SectionStart.insert(format!(".{}", name), parseAddr(&format!("-T{}", name), &arg.borrow()));
+        }
+        else if readArg("section-start") {
+            let arg = arg.borrow();
+            match arg.split_once('=') {
+                Some((name, addr)) => {
+                    ctx.Args.SectionStart.insert(name.into(), parseAddr("--section-start", addr));
+                },
+                None => {
+                    error!("invalid --section-start argument: {}", arg);
+                }
+            }
+        }
+        else if readArg("script") || readArg("T") {
+            let path = arg.borrow().clone();
+            let src = match std::fs::read_to_string(&path) {
+                Ok(src) => src,
+                Err(e) => {
+                    error!("cannot open linker script {}: {}", path, e);
+                }
+            };
+            ctx.Script = Some(LinkerScript::Parse(&path, &src));
+        }
+        else if readFlag("s") || readFlag("strip-all") {
+            ctx.Args.StripAll = true;
+        }
+        else if readFlag("S") || readFlag("strip-debug") {
+            ctx.Args.StripDebug = true;
+        }
+        else if readArg("unresolved-symbols") {
+            ctx.Args.UnresolvedSymbols = match arg.borrow().as_str() {
+                "report-all" => UnresolvedSymbols::ReportAll,
+                "ignore-all" => UnresolvedSymbols::IgnoreAll,
+                "ignore-in-object-files" => UnresolvedSymbols::IgnoreInObjectFiles,
+                other => {
+                    error!("unknown --unresolved-symbols argument: {}", other);
+                }
+            };
+        }
+        else if readArg("undefined") || readArg("u") {
+            ctx.Args.Undefined.push(arg.borrow().clone());
+        }
+        else if readFlag("warn-unresolved-symbols") {
+            ctx.Args.WarnUnresolvedSymbols = true;
+        }
+        else if readFlag("allow-multiple-definition") {
+            ctx.Args.AllowMultipleDefinition = true;
+        }
+        else if readFlag("no-relax") {
+            ctx.Args.NoRelax = true;
+        }
+        else if readFlag("relax") {
+            ctx.Args.NoRelax = false;
+        }
+        else if readFlag("gc-sections") {
+            ctx.Args.GcSections = true;
+        }
+        else if readFlag("no-gc-sections") {
+            ctx.Args.GcSections = false;
+        }
+        else if readFlag("print-gc-sections") {
+            ctx.Args.PrintGcSections = true;
+        }
+        else if readFlag("no-print-gc-sections") {
+            ctx.Args.PrintGcSections = false;
+        }
+        else if readArg("icf") {
+            ctx.Args.Icf = match arg.borrow().as_str() {
+                "none" => Icf::None,
+                "all" => Icf::All,
+                "safe" => Icf::Safe,
+                other => {
+                    error!("unknown --icf argument: {}", other);
+                }
+            };
+        }
+        else if readFlag("print-icf-sections") {
+            ctx.Args.PrintIcfSections = true;
+        }
+        else if readFlag("no-print-icf-sections") {
+            ctx.Args.PrintIcfSections = false;
+        }
+        else if readArg("z") {
+            match arg.borrow().as_str() {
+                "muldefs" => ctx.Args.AllowMultipleDefinition = true,
+                _ => { /* ignored */ }
+            }
+        }
         else if readArg("L") {
             ctx.Args.LIbraryPaths.push(arg.borrow().clone());
         }
         else if readArg("l") {
             remaining.push("-l".to_string() + &arg.borrow());
         }
+        // these apply to the input files that follow, so they stay in place
+        else if readFlag("static") || readFlag("Bstatic") || readFlag("dn") || readFlag("non_shared") {
+            remaining.push("-Bstatic".into());
+        }
+        else if readFlag("Bdynamic") || readFlag("dy") || readFlag("call_shared") {
+            remaining.push("-Bdynamic".into());
+        }
+        else if readFlag("as-needed") {
+            remaining.push("--as-needed".into());
+        }
+        else if readFlag("no-as-needed") {
+            remaining.push("--no-as-needed".into());
+        }
+        else if readArg("dynamic-linker") || readArg("I") {
+            ctx.Args.DynamicLinker = arg.borrow().clone();
+        }
+        // gcc passes it for -static-pie
+        else if readFlag("no-dynamic-linker") {
+            ctx.Args.DynamicLinker.clear();
+        }
+        else if readFlag("E") || readFlag("export-dynamic") {
+            ctx.Args.ExportDynamic = true;
+        }
+        else if readFlag("no-export-dynamic") {
+            ctx.Args.ExportDynamic = false;
+        }
+        else if readFlag("eh-frame-hdr") {
+            ctx.Args.EhFrameHdr = true;
+        }
+        else if readFlag("no-eh-frame-hdr") {
+            ctx.Args.EhFrameHdr = false;
+        }
+        // after the long options starting with e, which -eSYM would swallow
+        else if readArg("entry") || readArg("e") {
+            ctx.Args.Entry = arg.borrow().clone();
+            entrySet = true;
+        }
+        else if readArg("hash-style") {
+            ctx.Args.HashStyle = match arg.borrow().as_str() {
+                "sysv" => HashStyle::Sysv,
+                "gnu" => HashStyle::Gnu,
+                "both" => HashStyle::Both,
+                other => {
+                    error!("unknown --hash-style argument: {}", other);
+                }
+            };
+        }
         else if readArg("sysroot")
             || readArg("plugin")
             || readArg("plugin-opt")
-            || readArg("hash-style")
             || readArg("build-id")
-            || readArg("z")
-            || readFlag("static") 
-            || readFlag("s")
-            || readFlag("no-relax")
-            || readFlag("as-needed")
             || readFlag("start-group")
             || readFlag("end-group") { /*ignored */}
         else if readFlag("v") || readFlag("version"){
@@ -194,5 +383,18 @@ pub fn parseArgs(ctx: &mut Box<Context>) -> Vec<String> {
             *args = args[1..].into();
         }
     }
+
+    // a PIE is linked at 0, and moved by the loader
+    if ctx.Args.Pie && !imageBaseSet {
+        ctx.Args.ImageBase = 0;
+    }
+
+    // ENTRY in the script is weaker than -e
+    if let Some(script) = &ctx.Script {
+        if let (false, Some(entry)) = (entrySet, &script.Entry) {
+            ctx.Args.Entry = entry.clone();
+        }
+        ctx.Args.LIbraryPaths.extend(script.SearchDirs.iter().cloned());
+    }
     return remaining;
 }
\ No newline at end of file
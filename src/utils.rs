@@ -21,6 +21,9 @@ impl ByteSequence {
 	pub fn GetSlice(&self) -> &[u8] {
 		let ptr = self.0;
 		let len = self.1;
+		if ptr.is_null() {
+			return &[];
+		}
 		unsafe {std::slice::from_raw_parts(ptr, len)}
 	}
 }
@@ -109,6 +112,14 @@ pub fn atoi(s: &[u8]) -> usize {
 	s[0..end].parse::<usize>().unwrap()
 }
 
+/// parse a number given on the command line. `0x` prefix means hex
+pub fn ParseNumber(s: &str) -> Option<u64> {
+	match s.strip_prefix("0x").or(s.strip_prefix("0X")) {
+		Some(hex) => u64::from_str_radix(hex, 16).ok(),
+		None => s.parse::<u64>().ok()
+	}
+}
+
 pub fn vec2slice<T>(v: &Vec<T>) -> &[u8] {
     let len = v.len() * std::mem::size_of::<T>();
     let ptr = v.as_ptr() as *const u8;
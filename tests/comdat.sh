@@ -0,0 +1,38 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xassembler -
+	.section .text.inl,"axG",@progbits,inl,comdat
+	.globl inl
+inl:
+	li a0, 1
+	ret
+	.text
+	.globl _start
+_start:
+	call inl
+	call other
+EOF
+
+cat <<EOF | $CC -o "$t"/b.o -c -xassembler -
+	.section .text.inl,"axG",@progbits,inl,comdat
+	.globl inl
+inl:
+	li a0, 2
+	ret
+	.text
+	.globl other
+other:
+	call inl
+	ret
+EOF
+
+./ld "$t"/a.o "$t"/b.o -o "$t"/out
+[ "$(readelf -s "$t"/out | grep -c ' inl$')" = 1 ]
+objdump -d "$t"/out | grep -A1 '<inl>:' | grep -q 'li.*a0,1'
@@ -0,0 +1,23 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -
+void foo(void) {}
+void _start(void) {}
+EOF
+
+cat <<EOF | $CC -o "$t"/b.o -c -xc -
+void foo(void) {}
+EOF
+
+! ./ld "$t"/a.o "$t"/b.o -o "$t"/out > "$t"/log
+grep -q 'duplicate symbol: foo, defined in .*a.o and .*b.o' "$t"/log
+
+./ld --allow-multiple-definition "$t"/a.o "$t"/b.o -o "$t"/out
+./ld -z muldefs "$t"/a.o "$t"/b.o -o "$t"/out
@@ -0,0 +1,33 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -ffunction-sections -funwind-tables -
+int unused(int x) { return x * 3; }
+int foo(int x) { return x + 1; }
+void _start(void) { foo(1); }
+EOF
+
+cat <<EOF | $CC -o "$t"/b.o -c -xc -ffunction-sections -funwind-tables -
+int bar(int x) { return x - 1; }
+EOF
+
+./ld "$t"/a.o "$t"/b.o -o "$t"/out
+readelf -S "$t"/out | grep -q '\.eh_frame'
+! readelf -S "$t"/out | grep -q '\.eh_frame_hdr'
+
+# one CIE is shared by the FDEs of both files
+./ld "$t"/a.o "$t"/b.o --eh-frame-hdr -o "$t"/out
+readelf -l "$t"/out | grep -q GNU_EH_FRAME
+readelf -S "$t"/out | grep -q '\.eh_frame_hdr'
+[ "$(readelf --debug-dump=frames "$t"/out | grep -c ' CIE')" = 1 ]
+[ "$(readelf --debug-dump=frames "$t"/out | grep -c ' FDE')" = 4 ]
+
+# the FDE of the removed function is dropped
+./ld "$t"/a.o "$t"/b.o --eh-frame-hdr --gc-sections -o "$t"/out
+[ "$(readelf --debug-dump=frames "$t"/out | grep -c ' FDE')" = 2 ]
@@ -0,0 +1,32 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -
+void foo(void) {}
+void _start(void) {}
+EOF
+
+# foo comes first, so _start is not at the start of .text
+./ld "$t"/a.o -o "$t"/out
+entry=$(readelf -h "$t"/out | awk '/Entry point address/ { print $4 }')
+start=$(readelf -s "$t"/out | awk '$8 == "_start" { print $2 }')
+foo=$(readelf -s "$t"/out | awk '$8 == "foo" { print $2 }')
+[ "$start" != "$foo" ]
+[ $((entry)) = $((0x$start)) ]
+
+./ld -e foo "$t"/a.o -o "$t"/out
+entry=$(readelf -h "$t"/out | awk '/Entry point address/ { print $4 }')
+[ $((entry)) = $((0x$foo)) ]
+
+./ld -e 0x1234 "$t"/a.o -o "$t"/out
+readelf -h "$t"/out | grep -q 'Entry point address: *0x1234$'
+
+# not -e xport-dynamic
+./ld -export-dynamic "$t"/a.o -o "$t"/out > "$t"/log
+(! grep -q 'cannot find entry symbol' "$t"/log)
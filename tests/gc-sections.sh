@@ -0,0 +1,28 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -ffunction-sections -fdata-sections -
+int live_var = 1;
+int dead_var = 2;
+void live_fn(void) {}
+void dead_fn(void) {}
+void kept_fn(void) {}
+void _start(void) { live_fn(); live_var++; }
+EOF
+
+./ld --gc-sections "$t"/a.o -o "$t"/out
+readelf -s "$t"/out | grep -q live_fn
+readelf -s "$t"/out | grep -q live_var
+! readelf -s "$t"/out | grep -q dead_fn
+! readelf -s "$t"/out | grep -q dead_var
+
+./ld --gc-sections -u kept_fn "$t"/a.o -o "$t"/out2
+readelf -s "$t"/out2 | grep -q kept_fn
+
+./ld --gc-sections --print-gc-sections "$t"/a.o -o "$t"/out3 | grep -q "'.text.dead_fn'"
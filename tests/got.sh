@@ -0,0 +1,22 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -fPIC -
+int x = 3;
+extern int y __attribute__((weak));
+int foo(void) { return x; }
+void _start(void) { x = foo() + (&y != 0); }
+EOF
+
+./ld "$t"/a.o -o "$t"/out
+readelf -S "$t"/out | grep -q '\.got'
+readelf -r "$t"/out | grep -q 'no relocations'
+
+./ld -static-pie "$t"/a.o -o "$t"/out2
+readelf -r "$t"/out2 | grep -q R_RISCV_RELATIVE
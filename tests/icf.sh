@@ -0,0 +1,39 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -ffunction-sections -O2 -
+int foo(int x) { return x * 3 + 1; }
+int bar(int x) { return x * 3 + 1; }
+int (*fp)(int) = bar;
+int _start(void) { return foo(1) + bar(2); }
+EOF
+
+./ld --icf=all "$t"/a.o -o "$t"/out
+foo=$(readelf -s "$t"/out | grep ' foo$' | awk '{print $2}')
+bar=$(readelf -s "$t"/out | grep ' bar$' | awk '{print $2}')
+[ "$foo" = "$bar" ]
+
+# bar's address is taken
+./ld --icf=safe "$t"/a.o -o "$t"/out2
+foo=$(readelf -s "$t"/out2 | grep ' foo$' | awk '{print $2}')
+bar=$(readelf -s "$t"/out2 | grep ' bar$' | awk '{print $2}')
+[ "$foo" != "$bar" ]
+
+./ld --icf=all --print-icf-sections "$t"/a.o -o "$t"/out3 | grep -q 'removing identical section'
+
+# members of a C-identifier section are iterated with __start_/__stop_
+echo '__attribute__((section("foo"), used)) static const long a = 1;' | $CC -o "$t"/b.o -c -xc -
+echo '__attribute__((section("foo"), used)) static const long b = 1;' | $CC -o "$t"/c.o -c -xc -
+cat <<EOF | $CC -o "$t"/d.o -c -xc -
+extern const long __start_foo[], __stop_foo[];
+int _start(void) { return __stop_foo - __start_foo; }
+EOF
+
+./ld --icf=all "$t"/b.o "$t"/c.o "$t"/d.o -o "$t"/out4
+readelf -S -W "$t"/out4 | grep -q ' foo .* 000010 '
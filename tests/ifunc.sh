@@ -0,0 +1,28 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -
+static int impl(void) { return 3; }
+static void *resolve(void) { return impl; }
+int foo(void) __attribute__((ifunc("resolve")));
+int (*p)(void) = foo;
+// libc applies the relocations of static executables itself
+extern char __rela_iplt_start[], __rela_iplt_end[];
+long n;
+void _start(void) { n = __rela_iplt_end - __rela_iplt_start; foo(); }
+EOF
+
+./ld "$t"/a.o -o "$t"/out
+readelf -S "$t"/out | grep -q '\.iplt'
+readelf -r "$t"/out | grep -q R_RISCV_IRELATIVE
+# one Elf64_Rela
+start=$(readelf -s "$t"/out | awk '$8 == "__rela_iplt_start" { print $2 }')
+end=$(readelf -s "$t"/out | awk '$8 == "__rela_iplt_end" { print $2 }')
+[ $((0x$end - 0x$start)) = 24 ]
+readelf -s "$t"/out | grep ' foo$' | grep -q FUNC
@@ -0,0 +1,55 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -ffunction-sections -fdata-sections -
+extern char _sidata[], _sdata[], _edata[];
+int counter = 42;
+int zeroed;
+__attribute__((section(".vectors"))) int vectors[] = { 1, 2 };
+__attribute__((section(".unused"))) int unused = 3;
+void _start(void) { counter += _edata - _sdata + (long)_sidata; }
+EOF
+
+cat <<EOF > "$t"/a.ld
+ENTRY(_start)
+MEMORY
+{
+  FLASH (rx) : ORIGIN = 0x20000000, LENGTH = 64K
+  RAM (rwx)  : ORIGIN = 0x80000000, LENGTH = 16K
+}
+SECTIONS
+{
+  .text : { KEEP(*(.vectors)) *(.text .text.*) } > FLASH
+  .rodata : { *(.rodata .rodata.*) } > FLASH
+  _sidata = LOADADDR(.data);
+  .data : { _sdata = .; *(.sdata .sdata.* .data .data.*) _edata = .; } > RAM AT > FLASH
+  .bss (NOLOAD) : { *(.sbss .sbss.* .bss .bss.* COMMON) } > RAM
+  PROVIDE(_stack_top = ORIGIN(RAM) + LENGTH(RAM));
+  /DISCARD/ : { *(.unused) }
+}
+EOF
+
+./ld -T "$t"/a.ld --gc-sections "$t"/a.o -o "$t"/out
+readelf -S "$t"/out | grep -q '\.text.*PROGBITS.*0000000020000000'
+readelf -S "$t"/out | grep -q '\.data.*PROGBITS.*0000000080000000'
+readelf -S "$t"/out | grep -q '\.bss.*NOBITS'
+! readelf -S "$t"/out | grep -q '\.unused'
+# .data is loaded from flash, right after the read-only stuff
+readelf -l "$t"/out | grep LOAD | grep -q '0x0000000080000000 0x00000000200000'
+readelf -s "$t"/out | grep -q ' vectors$'
+readelf -s "$t"/out | grep _sidata | grep -q ' 00000000200000'
+! readelf -s "$t"/out | grep -q _stack_top
+
+cat <<EOF > "$t"/b.ld
+MEMORY { ROM : ORIGIN = 0x1000, LENGTH = 4 }
+SECTIONS { .text : { *(.text*) } > ROM }
+_sidata = 0; _sdata = 0; _edata = 0;
+EOF
+! ./ld -T "$t"/b.ld "$t"/a.o -o "$t"/out2 > "$t"/log
+grep -q "will not fit in region 'ROM'" "$t"/log
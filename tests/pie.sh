@@ -0,0 +1,31 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -fPIE -
+int x = 3;
+int *p = &x;
+void _start(void) { *p = 5; }
+EOF
+
+./ld -static-pie "$t"/a.o -o "$t"/out
+readelf -h "$t"/out | grep -q 'DYN'
+readelf -l "$t"/out | grep LOAD | head -1 | grep -q ' 0x0000000000000000 '
+readelf -l "$t"/out | grep -q DYNAMIC
+readelf -d "$t"/out | grep -q 'FLAGS_1.*PIE'
+readelf -d "$t"/out | grep -q SYMTAB
+readelf -r "$t"/out | grep -q R_RISCV_RELATIVE
+
+./ld -pie -no-pie "$t"/a.o -o "$t"/out2
+readelf -h "$t"/out2 | grep -q 'EXEC'
+
+# what gcc passes for -static-pie
+./ld -static -pie --no-dynamic-linker -z text "$t"/a.o -o "$t"/out3
+readelf -h "$t"/out3 | grep -q 'DYN'
+readelf -d "$t"/out3 | grep -q SYMTAB
+(! readelf -l "$t"/out3 | grep -q INTERP)
@@ -0,0 +1,60 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -
+int x = 3;
+int foo(void) { return x; }
+// gp relaxation needs gp to be set up, as crt1.o does
+void _start(void) {
+  asm volatile(".option push; .option norelax; lla gp, __global_pointer\$; .option pop");
+  foo();
+}
+EOF
+
+./ld "$t"/a.o -o "$t"/out
+objdump -d "$t"/out | grep -A8 '<_start>:' | grep -q 'jal'
+objdump -d "$t"/out | grep -A8 '<foo>:' | grep -q '(gp)'
+
+./ld --no-relax "$t"/a.o -o "$t"/out2
+objdump -d "$t"/out2 | grep -A8 '<_start>:' | grep -q 'auipc'
+
+# R_RISCV_ALIGN is honored even without relaxation
+cat <<EOF | $CC -o "$t"/b.o -c -xc -
+void _start(void) {}
+__attribute__((aligned(64))) void baz(void) {}
+EOF
+
+./ld --no-relax "$t"/b.o -o "$t"/out3
+readelf -s "$t"/out3 | grep -q '[048c]0 .* FUNC .* baz$'
+
+# .rodata ends right at gp - 2048 in one of these. shrinking .text moves it
+# away from gp, as .sdata is realigned
+for i in 0 1 2 3 4 5 6 7; do
+  cat <<EOF | $CC -o "$t"/c$i.o -c -xassembler -
+  .globl _start
+_start:
+  .option push
+  .option norelax
+  lla gp, __global_pointer\$
+  .option pop
+  call f
+  call f
+f:
+  lui a0, %hi(r)
+  addi a0, a0, %lo(r)
+  ret
+  .section .rodata,"a"
+  .zero $i
+r:
+  .section .sdata,"aw"
+  .p2align 3
+  .quad 1
+EOF
+  ./ld "$t"/c$i.o -o "$t"/out-c$i
+done
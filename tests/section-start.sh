@@ -0,0 +1,31 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -
+int x[64] = { 1 };
+int y[64];
+void _start(void) { y[0] = x[0]; }
+EOF
+
+./ld --image-base=0x400000 "$t"/a.o -o "$t"/out
+readelf -l "$t"/out | grep LOAD | head -1 | grep -q ' 0x0000000000400000 '
+
+./ld -Ttext=10000000 -Tdata 0x30000000 "$t"/a.o -o "$t"/out2
+readelf -S "$t"/out2 | grep -q '\.text.*0000000010000000'
+readelf -S "$t"/out2 | grep -q '\.data.*0000000030000000'
+readelf -l "$t"/out2 | grep LOAD | grep -q '0x0000000010000000'
+readelf -l "$t"/out2 | grep LOAD | grep -q '0x0000000030000000'
+
+# each segment sits in the file at the same page offset as in memory
+readelf -l "$t"/out2 | grep LOAD | while read -r _ off vaddr _; do
+  [ $(( off % 4096 )) = $(( vaddr % 4096 )) ]
+done
+
+./ld --section-start=.bss=0x50000000 "$t"/a.o -o "$t"/out3
+readelf -S "$t"/out3 | grep -q '\.bss.*NOBITS.*0000000050000000'
@@ -0,0 +1,37 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/b.so -shared -fPIC -Wl,-soname,libb.so.1 -xc -
+int bar = 3;
+int foo(void) { return bar; }
+int unused(void) { return 0; }
+EOF
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -fno-PIC -
+extern int bar;
+int foo(void);
+void _start(void) { bar = foo(); }
+EOF
+
+./ld "$t"/a.o "$t"/b.so -o "$t"/out
+readelf -l "$t"/out | grep -q 'Requesting program interpreter'
+readelf -d "$t"/out | grep -q 'NEEDED.*libb.so.1'
+readelf -r "$t"/out | grep -q R_RISCV_JUMP_SLOT
+readelf -r "$t"/out | grep -q R_RISCV_COPY
+readelf --dyn-syms "$t"/out | grep -q ' foo$'
+! readelf --dyn-syms "$t"/out | grep -q ' unused$'
+
+./ld -static "$t"/a.o "$t"/b.so -o "$t"/out2 2>&1 | grep -q 'attempted static link of dynamic object'
+
+cat <<EOF | $CC -o "$t"/c.o -c -xc -
+void _start(void) {}
+EOF
+
+./ld "$t"/c.o --as-needed "$t"/b.so -o "$t"/out3
+! readelf -d "$t"/out3 | grep -q NEEDED
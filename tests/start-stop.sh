@@ -0,0 +1,34 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -
+__attribute__((section("foo"))) long a = 1;
+extern long __start_foo[], __stop_foo[];
+long sum;
+void _start(void) {
+  for (long *p = __start_foo; p < __stop_foo; p++)
+    sum += *p;
+}
+EOF
+
+cat <<EOF | $CC -o "$t"/b.o -c -xc -
+__attribute__((section("foo"))) long b = 2;
+EOF
+
+# foo is kept as a whole even if nothing else refers to b
+for opt in "" --gc-sections; do
+  ./ld $opt "$t"/a.o "$t"/b.o -o "$t"/out
+  addr=$(readelf -S -W "$t"/out | grep ' foo ' | awk '{ print $(NF-7) }')
+  size=$(readelf -S -W "$t"/out | grep ' foo ' | awk '{ print $(NF-5) }')
+  start=$(readelf -s "$t"/out | awk '$8 == "__start_foo" { print $2 }')
+  stop=$(readelf -s "$t"/out | awk '$8 == "__stop_foo" { print $2 }')
+  [ $((0x$size)) = 16 ]
+  [ $((0x$start)) = $((0x$addr)) ]
+  [ $((0x$stop)) = $((0x$addr + 0x$size)) ]
+done
@@ -0,0 +1,38 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -
+static int bar(void) { return 1; }
+int foo(void) { return bar(); }
+void _start(void) { foo(); }
+EOF
+
+./ld "$t"/a.o -o "$t"/out
+readelf -s "$t"/out | grep -q 'GLOBAL .* foo$'
+readelf -s "$t"/out | grep -q 'LOCAL .* bar$'
+
+./ld -s "$t"/a.o -o "$t"/out2
+! readelf -S "$t"/out2 | grep -q symtab
+
+# hidden in one file is hidden in the output, and local
+cat <<EOF | $CC -o "$t"/b.o -c -xc -
+__attribute__((visibility("hidden"))) void baz(void);
+void _start(void) { baz(); }
+EOF
+
+cat <<EOF | $CC -o "$t"/c.o -c -xc -
+void baz(void) {}
+EOF
+
+./ld "$t"/b.o "$t"/c.o -o "$t"/out3
+readelf -s "$t"/out3 | grep -q 'LOCAL *HIDDEN .* baz$'
+# sh_info is the number of local symbols
+info=$(readelf -S -W "$t"/out3 | grep ' \.symtab ' | awk '{ print $(NF-1) }')
+idx=$(readelf -s "$t"/out3 | awk '$8 == "baz" { print $1 }' | tr -d :)
+[ "$idx" -lt "$info" ]
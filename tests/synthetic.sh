@@ -0,0 +1,27 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -
+extern char _end[], __bss_start[];
+char *p, *q;
+void _start(void) { p = _end; q = __bss_start; }
+EOF
+
+./ld "$t"/a.o -o "$t"/out
+readelf -s "$t"/out | grep -q ' ABS _end$'
+readelf -s "$t"/out | grep -q ' ABS __bss_start$'
+
+# a definition in an input file wins, even a weak one
+cat <<EOF | $CC -o "$t"/b.o -c -xc -
+__attribute__((weak)) char _end[8];
+EOF
+
+./ld "$t"/a.o "$t"/b.o -o "$t"/out2
+(! readelf -s "$t"/out2 | grep -q ' ABS _end$')
+readelf -s "$t"/out2 | grep -q ' ABS __bss_start$'
@@ -0,0 +1,31 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -fcommon -ftls-model=local-exec -
+_Thread_local char a = 1;
+_Thread_local int b __attribute__((aligned(64)));
+_Thread_local long c __attribute__((common));
+long d;
+void _start(void) { b = a + c + d; }
+EOF
+
+./ld "$t"/a.o -o "$t"/out
+
+# glibc copies p_filesz bytes of the template to tp, and clears the rest up
+# to p_memsz. tp is aligned to p_align, so the template must be aligned too
+read -r vaddr filesz memsz align <<< "$(readelf -lW "$t"/out | awk '$1 == "TLS" { print $3, $5, $6, $8 }')"
+[ $((vaddr % align)) -eq 0 ]
+[ $((align)) -eq 64 ]
+[ $((filesz)) -ge 1 ]
+[ $((memsz)) -gt $((filesz)) ]
+
+# the common TLS variable lives in .tbss, inside PT_TLS
+c=$(readelf -sW "$t"/out | awk '$8 == "c" { print "0x" $2 }')
+[ $((c)) -ge $((vaddr)) ] && [ $((c)) -lt $((vaddr + memsz)) ]
+readelf -sW "$t"/out | awk '$8 == "d"' | grep -q OBJECT
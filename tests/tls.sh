@@ -0,0 +1,54 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -ftls-model=local-exec -
+_Thread_local int x = 3;
+_Thread_local int y;
+void _start(void) { y = x; }
+EOF
+
+cat <<EOF | $CC -o "$t"/b.o -c -xc -fPIC -ftls-model=initial-exec -
+extern _Thread_local int y;
+int load_y(void) { return y; }
+EOF
+
+cat <<EOF | $CC -o "$t"/c.o -c -xc -fPIC -ftls-model=global-dynamic -
+extern _Thread_local int y;
+int *get_y(void) { return &y; }
+void *__tls_get_addr(void *p) { return p; }
+EOF
+
+./ld "$t"/a.o "$t"/b.o "$t"/c.o -o "$t"/out
+readelf -l "$t"/out | grep -q TLS
+readelf -S "$t"/out | grep -q '\.got'
+
+# offsets from tp. .tbss follows .tdata, so y is not at 0
+tls=$(readelf -l -W "$t"/out | awk '$1 == "TLS" { print $3 }')
+x=$(readelf -s "$t"/out | awk '$8 == "x" { print $2 }')
+y=$(readelf -s "$t"/out | awk '$8 == "y" { print $2 }')
+xoff=$((0x$x - tls))
+yoff=$((0x$y - tls))
+
+# local-exec
+objdump -d "$t"/out | awk '/<_start>:/,/^$/' > "$t"/_start
+grep -Eq "lw\s+[a-z0-9]+,$xoff\(" "$t"/_start
+grep -Eq "sw\s+[a-z0-9]+,$yoff\(" "$t"/_start
+
+# initial-exec loads the offset from the GOT
+objdump -d "$t"/out | awk '/<load_y>:/,/^$/' | grep -Eq 'ld\s+([a-z0-9]+),-?[0-9]+\(\1\)'
+got=$(readelf -S -W "$t"/out | grep ' \.got ' | awk '{ print $(NF-6) }')
+[ $(($(od -An -t d8 -j $((0x$got)) -N 8 "$t"/out))) = $yoff ]
+
+# global-dynamic becomes local-exec. lui replaces auipc, and the call to
+# __tls_get_addr is replaced by adding tp
+objdump -d "$t"/out | awk '/<get_y>:/,/^$/' > "$t"/get_y
+grep -Eq 'lui\s+a0,' "$t"/get_y
+(! grep -q auipc "$t"/get_y)
+grep -Eq "addi\s+a0,a0,$yoff" "$t"/get_y
+grep -Eq 'add\s+a0,a0,tp' "$t"/get_y
@@ -0,0 +1,35 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -
+void foo(void);
+void _start(void) { foo(); }
+EOF
+
+! ./ld "$t"/a.o -o "$t"/out > "$t"/log
+grep -q 'undefined symbol: foo' "$t"/log
+grep -q 'referenced by .*a.o' "$t"/log
+
+./ld --warn-unresolved-symbols "$t"/a.o -o "$t"/out
+./ld --unresolved-symbols=ignore-all "$t"/a.o -o "$t"/out
+
+# shared libraries are checked even if objects are not
+cat <<EOF | $CC -o "$t"/b.so -shared -fPIC -nostdlib -xc -
+void bar(void);
+void baz(void) { bar(); }
+EOF
+
+cat <<EOF | $CC -o "$t"/c.o -c -xc -
+void baz(void);
+void _start(void) { baz(); }
+EOF
+
+! ./ld --unresolved-symbols=ignore-in-object-files "$t"/c.o "$t"/b.so -o "$t"/out > "$t"/log2
+grep -q 'undefined symbol: bar' "$t"/log2
+./ld --unresolved-symbols=ignore-in-object-files "$t"/a.o -o "$t"/out
@@ -0,0 +1,48 @@
+#!/bin/bash
+CC=riscv64-linux-gnu-gcc-10
+test_name=$(basename "$0" .sh)
+t=out/tests/$test_name
+
+set -e
+
+mkdir -p "$t"
+
+cat <<EOF | $CC -o "$t"/a.o -c -xc -
+__attribute__((weak)) int foo(void) { return 1; }
+void _start(void) { foo(); }
+EOF
+
+cat <<EOF | $CC -o "$t"/b.o -c -xc -O2 -
+int foo(void) { return 2; }
+EOF
+
+cat <<EOF | $CC -o "$t"/c.o -c -xc -
+__attribute__((weak)) int foo(void) { return 3; }
+EOF
+
+# the strong definition wins
+./ld "$t"/a.o "$t"/b.o -o "$t"/out
+readelf -s "$t"/out | grep -q 'GLOBAL .* foo$'
+objdump -d "$t"/out | grep -A2 '<foo>:' | grep -q 'li\s*a0,2'
+
+# two weak ones are not duplicates
+./ld "$t"/a.o "$t"/c.o -o "$t"/out2 > "$t"/log
+(! grep -q 'duplicate symbol' "$t"/log)
+readelf -s "$t"/out2 | grep -q 'WEAK .* foo$'
+
+# an undefined weak symbol is 0, and pulls no archive member in
+cat <<EOF | $CC -o "$t"/d.o -c -xc -
+__attribute__((weak)) void bar(void);
+void (*p)(void) = bar;
+void _start(void) {}
+EOF
+
+cat <<EOF | $CC -o "$t"/e.o -c -xc -
+void bar(void) {}
+EOF
+
+rm -f "$t"/libe.a
+ar rcs "$t"/libe.a "$t"/e.o
+./ld "$t"/d.o "$t"/libe.a -o "$t"/out3
+(! readelf -s "$t"/out3 | grep -q ' bar$')
+readelf -x .data "$t"/out3 | grep -q ' 00000000 00000000 '